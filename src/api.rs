@@ -4,9 +4,15 @@
  * @copyright 2024 © by Rafał Wrzeszcz - Wrzasq.pl.
  */
 
-use crate::model::{Report, VesselReportPageToken};
+use crate::model::{
+    Ingestion, IngestionKey, NormalizedValue, Report, ReportSource, ReportValue, VesselReportPageToken,
+};
+use crate::runtime_error::RuntimeError;
+use chrono::NaiveDate;
+use log::warn;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{BTreeSet, HashMap};
+use std::future::Future;
 use uuid::Uuid;
 use wrzasqpl_commons_aws::DynamoDbResultsPage;
 
@@ -17,26 +23,357 @@ use wrzasqpl_commons_aws::DynamoDbResultsPage;
 pub struct FetchRequest {
     pub customer_id: Uuid,
     pub vessel_id: Uuid,
-    pub report_name: String,
+    pub report_name: Option<String>,
+    pub from: Option<NaiveDate>,
+    pub to: Option<NaiveDate>,
     pub page_token: Option<String>,
+    #[serde(default)]
+    pub include_source: bool,
+    #[serde(default)]
+    pub include_units: bool,
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct FieldUnit {
+    pub unit: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub normalized: Option<NormalizedValue>,
 }
 
 #[derive(Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct ReportResponse {
-    pub fields: HashMap<String, String>, // TODO: switch value type to numeric type?
+    pub reports: HashMap<String, HashMap<String, ReportValue>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sources: Option<HashMap<String, HashMap<String, ReportSource>>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub units: Option<HashMap<String, HashMap<String, FieldUnit>>>,
+    pub page_token: Option<String>,
+}
+
+impl ReportResponse {
+    #[doc = "Groups fields by report, provenance and units of values are included only on request. Fields of sensors \
+that have canonical name in the catalog are exposed under that name."]
+    pub fn new(
+        page: DynamoDbResultsPage<Report, VesselReportPageToken>,
+        include_source: bool,
+        include_units: bool,
+        canonical_names: &HashMap<String, String>,
+    ) -> Self {
+        let mut reports: HashMap<String, HashMap<String, ReportValue>> = HashMap::new();
+        let mut sources: HashMap<String, HashMap<String, ReportSource>> = HashMap::new();
+        let mut units: HashMap<String, HashMap<String, FieldUnit>> = HashMap::new();
+
+        for field in page.items {
+            let field_name = canonical_names
+                .get(&field.field_name)
+                .cloned()
+                .unwrap_or(field.field_name);
+
+            // items loaded before provenance was tracked have no source
+            if let (true, Some(source)) = (include_source, field.source) {
+                sources
+                    .entry(field.report_name.clone())
+                    .or_default()
+                    .insert(field_name.clone(), source);
+            }
+
+            // fields without recognized unit are left out
+            if let (true, Some(unit)) = (include_units, field.unit) {
+                units.entry(field.report_name.clone()).or_default().insert(
+                    field_name.clone(),
+                    FieldUnit {
+                        unit,
+                        normalized: field.normalized,
+                    },
+                );
+            }
+
+            reports
+                .entry(field.report_name)
+                .or_default()
+                .insert(field_name, field.value);
+        }
+
+        Self {
+            reports,
+            sources: include_source.then_some(sources),
+            units: include_units.then_some(units),
+            page_token: page.last_evaluated_key.map(|key| key.report_key),
+        }
+    }
+}
+
+impl ReportResponse {
+    #[doc = "Looks up canonical names only of the fields present in the page. Names don't change the values, so when \
+the lookup fails fields are exposed under raw keys instead of failing whole fetch."]
+    pub async fn resolve<F, R>(
+        page: DynamoDbResultsPage<Report, VesselReportPageToken>,
+        include_source: bool,
+        include_units: bool,
+        canonical_names_of: F,
+    ) -> Self
+    where
+        F: FnOnce(Vec<String>) -> R,
+        R: Future<Output = Result<HashMap<String, String>, RuntimeError>>,
+    {
+        let field_names = page
+            .items
+            .iter()
+            .map(|field| field.field_name.clone())
+            .collect::<BTreeSet<String>>();
+
+        let canonical_names = match field_names.is_empty() {
+            true => HashMap::new(),
+            false => canonical_names_of(field_names.into_iter().collect())
+                .await
+                .unwrap_or_else(|error| {
+                    warn!("Could not resolve canonical sensor names: {}", error);
+                    HashMap::new()
+                }),
+        };
+
+        Self::new(page, include_source, include_units, &canonical_names)
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct IngestionsRequest {
+    pub customer_id: Uuid,
+    pub vessel_id: Uuid,
+    pub page_token: Option<String>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct IngestionsResponse {
+    pub ingestions: Vec<Ingestion>,
     pub page_token: Option<String>,
 }
 
-impl From<DynamoDbResultsPage<Report, VesselReportPageToken>> for ReportResponse {
-    fn from(value: DynamoDbResultsPage<Report, VesselReportPageToken>) -> Self {
+impl From<DynamoDbResultsPage<Ingestion, IngestionKey>> for IngestionsResponse {
+    fn from(value: DynamoDbResultsPage<Ingestion, IngestionKey>) -> Self {
         Self {
-            fields: value
-                .items
-                .into_iter()
-                .map(|field| (field.field_name, field.value))
-                .collect(),
-            page_token: value.last_evaluated_key.map(|key| key.field_name),
+            ingestions: value.items,
+            page_token: value.last_evaluated_key.map(|key| key.object_id),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::api::{FieldUnit, ReportResponse};
+    use crate::model::{NormalizedValue, Report, ReportSource, ReportValue, VesselReportPageToken};
+    use crate::runtime_error::RuntimeError;
+    use chrono::DateTime;
+    use std::collections::HashMap;
+    use uuid::{uuid, Uuid};
+    use wrzasqpl_commons_aws::DynamoDbResultsPage;
+
+    const CUSTOMER_ID: Uuid = uuid!("00000000-0000-0000-0000-000000000000");
+    const VESSEL_ID: Uuid = uuid!("00000000-0000-0000-0000-000000000001");
+
+    fn report(report_name: &str, field_name: &str, value: f64) -> Report {
+        Report {
+            customer_id: CUSTOMER_ID,
+            vessel_id: VESSEL_ID,
+            report_name: report_name.into(),
+            field_name: field_name.into(),
+            value: ReportValue::Number(value),
+            label: "".into(),
+            source: None,
+            unit: None,
+            normalized: None,
+        }
+    }
+
+    #[test]
+    fn group_fields_by_report() {
+        let response = ReportResponse::new(
+            DynamoDbResultsPage::<Report, VesselReportPageToken> {
+                items: vec![
+                    report("2024-03-01.daily", "1", 10.0),
+                    report("2024-03-01.daily", "2", 20.0),
+                    report("2024-03-02.daily", "1", 11.0),
+                ],
+                last_evaluated_key: None,
+            },
+            false,
+            false,
+            &HashMap::new(),
+        );
+
+        assert_eq!(2, response.reports.len());
+        assert_eq!(ReportValue::Number(10.0), response.reports["2024-03-01.daily"]["1"]);
+        assert_eq!(ReportValue::Number(20.0), response.reports["2024-03-01.daily"]["2"]);
+        assert_eq!(ReportValue::Number(11.0), response.reports["2024-03-02.daily"]["1"]);
+        assert!(response.page_token.is_none());
+        assert!(response.sources.is_none());
+        assert!(response.units.is_none());
+    }
+
+    #[test]
+    fn include_sources() {
+        let source = ReportSource {
+            bucket_name: "upload".into(),
+            object_key: "v1/SYNC/sync.zip".into(),
+            version_id: None,
+            entry_path: "data/reports.json".into(),
+            recorded_at: DateTime::from_timestamp(1709251200, 0).unwrap(),
+            ingested_at: DateTime::from_timestamp(1709337600, 0).unwrap(),
+            timezone: Some("UTC".into()),
+        };
+        let mut traced = report("2024-03-01.daily", "1", 10.0);
+        traced.source = Some(source.clone());
+
+        let response = ReportResponse::new(
+            DynamoDbResultsPage::<Report, VesselReportPageToken> {
+                items: vec![traced, report("2024-03-01.daily", "2", 20.0)],
+                last_evaluated_key: None,
+            },
+            true,
+            false,
+            &HashMap::new(),
+        );
+        let sources = response.sources.unwrap();
+
+        assert_eq!(2, response.reports["2024-03-01.daily"].len());
+        assert_eq!(source, sources["2024-03-01.daily"]["1"]);
+        // legacy item without provenance
+        assert!(!sources["2024-03-01.daily"].contains_key("2"));
+    }
+
+    #[test]
+    fn resolve_canonical_names() {
+        let mut traced = report("2024-03-01.daily", "1", 10.0);
+        traced.source = Some(ReportSource {
+            bucket_name: "upload".into(),
+            object_key: "v1/SYNC/sync.zip".into(),
+            version_id: None,
+            entry_path: "data/reports.json".into(),
+            recorded_at: DateTime::from_timestamp(1709251200, 0).unwrap(),
+            ingested_at: DateTime::from_timestamp(1709337600, 0).unwrap(),
+            timezone: None,
+        });
+
+        let response = ReportResponse::new(
+            DynamoDbResultsPage::<Report, VesselReportPageToken> {
+                items: vec![traced, report("2024-03-01.daily", "2", 20.0)],
+                last_evaluated_key: None,
+            },
+            true,
+            false,
+            &HashMap::from([("1".into(), "fuel_consumption".into())]),
+        );
+
+        assert_eq!(
+            ReportValue::Number(10.0),
+            response.reports["2024-03-01.daily"]["fuel_consumption"]
+        );
+        // sensors without canonical name keep raw key
+        assert_eq!(ReportValue::Number(20.0), response.reports["2024-03-01.daily"]["2"]);
+        assert!(!response.reports["2024-03-01.daily"].contains_key("1"));
+        assert!(response.sources.unwrap()["2024-03-01.daily"].contains_key("fuel_consumption"));
+    }
+
+    #[test]
+    fn include_units() {
+        let mut converted = report("2024-03-01.daily", "1", 10.0);
+        converted.unit = Some("kW".into());
+        converted.normalized = Some(NormalizedValue {
+            value: ReportValue::Number(10000.0),
+            unit: "W".into(),
+        });
+        let mut unconverted = report("2024-03-01.daily", "2", 20.0);
+        unconverted.unit = Some("%".into());
+
+        let response = ReportResponse::new(
+            DynamoDbResultsPage::<Report, VesselReportPageToken> {
+                items: vec![converted, unconverted, report("2024-03-01.daily", "3", 30.0)],
+                last_evaluated_key: None,
+            },
+            false,
+            true,
+            &HashMap::from([("1".into(), "main_engine_power".into())]),
+        );
+        let units = response.units.unwrap();
+
+        // original value is kept in the report
+        assert_eq!(
+            ReportValue::Number(10.0),
+            response.reports["2024-03-01.daily"]["main_engine_power"]
+        );
+        assert_eq!(
+            FieldUnit {
+                unit: "kW".into(),
+                normalized: Some(NormalizedValue {
+                    value: ReportValue::Number(10000.0),
+                    unit: "W".into(),
+                }),
+            },
+            units["2024-03-01.daily"]["main_engine_power"]
+        );
+        assert_eq!(
+            FieldUnit {
+                unit: "%".into(),
+                normalized: None,
+            },
+            units["2024-03-01.daily"]["2"]
+        );
+        assert!(!units["2024-03-01.daily"].contains_key("3"));
+        assert!(response.sources.is_none());
+    }
+
+    fn page(items: Vec<Report>) -> DynamoDbResultsPage<Report, VesselReportPageToken> {
+        DynamoDbResultsPage::<Report, VesselReportPageToken> {
+            items,
+            last_evaluated_key: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn resolve_page_canonical_names() {
+        let response = ReportResponse::resolve(
+            page(vec![
+                report("2024-03-01.daily", "2", 20.0),
+                report("2024-03-01.daily", "1", 10.0),
+                report("2024-03-02.daily", "1", 11.0),
+            ]),
+            false,
+            false,
+            |field_names| async move {
+                // each field is looked up once
+                assert_eq!(vec!["1".to_string(), "2".to_string()], field_names);
+                Ok(HashMap::from([("1".into(), "fuel_consumption".into())]))
+            },
+        )
+        .await;
+
+        assert_eq!(
+            ReportValue::Number(11.0),
+            response.reports["2024-03-02.daily"]["fuel_consumption"]
+        );
+        assert_eq!(ReportValue::Number(20.0), response.reports["2024-03-01.daily"]["2"]);
+    }
+
+    #[tokio::test]
+    async fn resolve_without_canonical_names() {
+        let response = ReportResponse::resolve(
+            page(vec![report("2024-03-01.daily", "1", 10.0)]),
+            false,
+            false,
+            |_| async { Err(RuntimeError::UnprocessedItems(1)) },
+        )
+        .await;
+
+        // lookup failure doesn't fail the fetch
+        assert_eq!(ReportValue::Number(10.0), response.reports["2024-03-01.daily"]["1"]);
+
+        let response =
+            ReportResponse::resolve(page(vec![]), false, false, |_| async { panic!("Lookup of empty page") }).await;
+
+        assert!(response.reports.is_empty());
+    }
+}
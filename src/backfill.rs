@@ -0,0 +1,331 @@
+/*
+ * This file is part of the IVMS Online.
+ *
+ * @copyright 2024 © by Rafał Wrzeszcz - Wrzasq.pl.
+ */
+
+use crate::events::{ObjectEvent, ObjectRef};
+use crate::loader::{load_reports, LoaderConfig};
+use crate::parsers::SyncFormats;
+use crate::runtime_error::RuntimeError;
+use aws_sdk_dynamodb::Client as DynamoDbClient;
+use aws_sdk_s3::Client as S3Client;
+use futures::{stream, StreamExt};
+use log::{error, info};
+use serde::Serialize;
+use std::fs::{read_to_string, rename, write};
+use std::future::Future;
+use std::io::ErrorKind;
+use std::path::PathBuf;
+use wrzasqpl_commons_aws::DynamoDbDao;
+
+static DEFAULT_CONCURRENCY: usize = 4;
+
+#[derive(Debug, PartialEq)]
+#[doc = "Options of reprocessing S3 prefix."]
+pub struct BackfillOptions {
+    pub bucket_name: String,
+    #[doc = "Key prefix of reprocessed objects, eg. `v1/SYNC/{customerId}/`."]
+    pub prefix: String,
+    #[doc = "Number of objects loaded at once."]
+    pub concurrency: usize,
+    #[doc = "File in which progress is stored, so that interrupted run can be resumed."]
+    pub checkpoint: Option<PathBuf>,
+    #[doc = "Only lists objects that would be loaded."]
+    pub dry_run: bool,
+    #[doc = "Loads objects again, even if they are already recorded in the ledger."]
+    pub reload: bool,
+}
+
+impl BackfillOptions {
+    #[doc = "Parses command line arguments (without program name)."]
+    pub fn from_args(args: impl IntoIterator<Item = String>) -> Result<Self, RuntimeError> {
+        let mut bucket_name = None;
+        let mut prefix = None;
+        let mut options = Self {
+            bucket_name: String::new(),
+            prefix: String::new(),
+            concurrency: DEFAULT_CONCURRENCY,
+            checkpoint: None,
+            dry_run: false,
+            reload: false,
+        };
+
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            let mut value = || {
+                args.next()
+                    .ok_or_else(|| RuntimeError::InvalidArguments(format!("missing value of {arg}")))
+            };
+
+            match arg.as_str() {
+                "--bucket" => bucket_name = Some(value()?),
+                "--prefix" => prefix = Some(value()?),
+                "--concurrency" => options.concurrency = value()?.parse::<usize>()?.max(1),
+                "--checkpoint" => options.checkpoint = Some(value()?.into()),
+                "--dry-run" => options.dry_run = true,
+                "--reload" => options.reload = true,
+                _ => return Err(RuntimeError::InvalidArguments(format!("unknown option {arg}"))),
+            }
+        }
+
+        options.bucket_name = bucket_name.ok_or(RuntimeError::InvalidArguments("--bucket is required".into()))?;
+        options.prefix = prefix.ok_or(RuntimeError::InvalidArguments("--prefix is required".into()))?;
+
+        Ok(options)
+    }
+}
+
+// last key up to which all objects were processed, keys are listed in lexicographical order
+struct Checkpoint {
+    path: Option<PathBuf>,
+}
+
+impl Checkpoint {
+    fn load(&self) -> Result<Option<String>, RuntimeError> {
+        let Some(path) = &self.path else {
+            return Ok(None);
+        };
+
+        match read_to_string(path) {
+            Ok(key) => Ok(Some(key.trim().to_string()).filter(|key| !key.is_empty())),
+            Err(error) if error.kind() == ErrorKind::NotFound => Ok(None),
+            Err(error) => Err(error.into()),
+        }
+    }
+
+    fn save(&self, object_key: &str) -> Result<(), RuntimeError> {
+        if let Some(path) = &self.path {
+            // replaced atomically, so that interruption never leaves partially written checkpoint
+            let temporary = path.with_extension("tmp");
+            write(&temporary, object_key)?;
+            rename(temporary, path)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Default, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+#[doc = "Result of reprocessing S3 prefix."]
+pub struct BackfillSummary {
+    #[doc = "Number of objects loaded (or, in dry run, that would be loaded)."]
+    pub processed: usize,
+    #[doc = "Keys of objects that failed to load."]
+    pub failed: Vec<String>,
+    #[doc = "Key up to which all objects were processed."]
+    pub checkpoint: Option<String>,
+}
+
+// objects are processed concurrently, but results are consumed in listing order, so that checkpoint never skips
+// an unfinished object
+async fn process_keys<F, R>(
+    summary: &mut BackfillSummary,
+    checkpoint: &Checkpoint,
+    keys: Vec<String>,
+    concurrency: usize,
+    process: F,
+) -> Result<(), RuntimeError>
+where
+    F: Fn(String) -> R,
+    R: Future<Output = Result<(), RuntimeError>>,
+{
+    let mut results = stream::iter(keys)
+        .map(|object_key| {
+            let processing = process(object_key.clone());
+            async move { (object_key, processing.await) }
+        })
+        .buffered(concurrency);
+
+    while let Some((object_key, result)) = results.next().await {
+        match result {
+            Ok(()) => {
+                summary.processed += 1;
+
+                // failed object needs to be retried by next run, so progress is no longer recorded
+                if summary.failed.is_empty() {
+                    checkpoint.save(&object_key)?;
+                    summary.checkpoint = Some(object_key);
+                }
+            }
+            Err(error) => {
+                error!("Failed to load {}: {}.", object_key, error);
+                summary.failed.push(object_key);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[doc = "Loads all objects under given prefix, same way as if they were just uploaded."]
+pub async fn run_backfill(
+    s3: &S3Client,
+    dynamodb: &DynamoDbClient,
+    ledger: &DynamoDbDao,
+    formats: &SyncFormats,
+    config: &LoaderConfig,
+    options: &BackfillOptions,
+) -> Result<BackfillSummary, RuntimeError> {
+    let checkpoint = Checkpoint {
+        // dry run doesn't make any progress
+        path: options.checkpoint.clone().filter(|_| !options.dry_run),
+    };
+    let mut summary = BackfillSummary {
+        checkpoint: checkpoint.load()?,
+        ..BackfillSummary::default()
+    };
+    let mut continuation_token = None;
+
+    loop {
+        let page = s3
+            .list_objects_v2()
+            .bucket(options.bucket_name.as_str())
+            .prefix(options.prefix.as_str())
+            .set_start_after(summary.checkpoint.clone())
+            .set_continuation_token(continuation_token)
+            .send()
+            .await?;
+
+        let keys = page
+            .contents()
+            .iter()
+            .filter_map(|object| object.key().map(String::from))
+            .collect();
+
+        process_keys(
+            &mut summary,
+            &checkpoint,
+            keys,
+            options.concurrency,
+            |object_key| async {
+                if options.dry_run {
+                    info!("Would load {}.", object_key);
+                    return Ok(());
+                }
+
+                load_reports(
+                    s3,
+                    dynamodb,
+                    ledger,
+                    formats,
+                    config,
+                    ObjectRef {
+                        bucket_name: options.bucket_name.clone(),
+                        object_key,
+                        event: ObjectEvent::Created,
+                    },
+                )
+                .await
+                .map(drop)
+            },
+        )
+        .await?;
+
+        continuation_token = page.next_continuation_token;
+        if continuation_token.is_none() {
+            return Ok(summary);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::backfill::{process_keys, BackfillOptions, BackfillSummary, Checkpoint};
+    use crate::runtime_error::RuntimeError;
+    use std::env::temp_dir;
+    use std::fs::{read_to_string, remove_file};
+    use std::time::Duration;
+    use tokio::time::sleep;
+
+    fn args(args: &str) -> Result<BackfillOptions, RuntimeError> {
+        BackfillOptions::from_args(args.split_whitespace().map(String::from))
+    }
+
+    #[test]
+    fn parse_args() {
+        assert_eq!(
+            BackfillOptions {
+                bucket_name: "upload".into(),
+                prefix: "v1/SYNC/".into(),
+                concurrency: 8,
+                checkpoint: Some("backfill.checkpoint".into()),
+                dry_run: true,
+                reload: true,
+            },
+            args(
+                "--bucket upload --prefix v1/SYNC/ --concurrency 8 --checkpoint backfill.checkpoint --dry-run --reload"
+            )
+            .unwrap()
+        );
+
+        let defaults = args("--prefix v1/SYNC/ --bucket upload").unwrap();
+        assert_eq!(4, defaults.concurrency);
+        assert!(defaults.checkpoint.is_none());
+        assert!(!defaults.dry_run);
+        assert!(!defaults.reload);
+    }
+
+    #[test]
+    fn parse_invalid_args() {
+        assert!(matches!(
+            args("--bucket upload"),
+            Err(RuntimeError::InvalidArguments(_))
+        ));
+        assert!(matches!(
+            args("--bucket upload --prefix v1/ --force"),
+            Err(RuntimeError::InvalidArguments(_))
+        ));
+        assert!(matches!(
+            args("--bucket upload --prefix"),
+            Err(RuntimeError::InvalidArguments(_))
+        ));
+        assert!(matches!(
+            args("--bucket upload --prefix v1/ --concurrency x"),
+            Err(RuntimeError::ParseIntError(_))
+        ));
+    }
+
+    #[test]
+    fn checkpoint_file() {
+        let path = temp_dir().join(format!("ivms-backfill-{}.checkpoint", std::process::id()));
+        let checkpoint = Checkpoint {
+            path: Some(path.clone()),
+        };
+
+        assert!(checkpoint.load().unwrap().is_none());
+        checkpoint.save("v1/SYNC/a.zip").unwrap();
+        assert_eq!(Some("v1/SYNC/a.zip".into()), checkpoint.load().unwrap());
+        assert_eq!("v1/SYNC/a.zip", read_to_string(&path).unwrap());
+
+        remove_file(path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn ordered_progress() {
+        let mut summary = BackfillSummary::default();
+        let keys = ["a", "b", "c", "d", "e"].map(String::from).to_vec();
+
+        process_keys(&mut summary, &Checkpoint { path: None }, keys, 3, |key| async move {
+            // earlier keys finish last, yet checkpoint must not pass them
+            sleep(Duration::from_millis(match key.as_str() {
+                "a" => 30,
+                "b" => 20,
+                _ => 1,
+            }))
+            .await;
+
+            match key.as_str() {
+                "c" => Err(RuntimeError::UnrecognizedInput),
+                _ => Ok(()),
+            }
+        })
+        .await
+        .unwrap();
+
+        assert_eq!(4, summary.processed);
+        assert_eq!(vec!["c"], summary.failed);
+        assert_eq!(Some("b".into()), summary.checkpoint);
+    }
+}
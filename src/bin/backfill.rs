@@ -0,0 +1,62 @@
+/*
+ * This file is part of the IVMS Online.
+ *
+ * @copyright 2024 © by Rafał Wrzeszcz - Wrzasq.pl.
+ */
+
+use aws_config::{load_defaults, BehaviorVersion};
+use aws_sdk_dynamodb::config::Builder as DynamoDbConfigBuilder;
+use aws_sdk_dynamodb::Client as DynamoDbClient;
+use aws_sdk_s3::config::Builder as S3ConfigBuilder;
+use aws_sdk_s3::Client as S3Client;
+use env_logger::Env;
+use ivms_reports_aggregator::backfill::{run_backfill, BackfillOptions};
+use ivms_reports_aggregator::loader::LoaderConfig;
+use ivms_reports_aggregator::parsers::SyncFormats;
+use lambda_runtime::Error;
+use serde_json::to_string;
+use std::env::{args, var};
+use std::process::exit;
+use tokio::main as tokio_main;
+use wrzasqpl_commons_aws::DynamoDbDao;
+
+#[tokio_main]
+async fn main() -> Result<(), Error> {
+    env_logger::Builder::from_env(Env::default().default_filter_or("info")).init();
+
+    let options = BackfillOptions::from_args(args().skip(1))?;
+    let config = &load_defaults(BehaviorVersion::v2023_11_09()).await;
+
+    // custom endpoints allow running against local stand-ins, like MinIO and DynamoDB Local
+    let mut s3_config = S3ConfigBuilder::from(config);
+    if let Ok(endpoint) = var("S3_ENDPOINT") {
+        s3_config = s3_config.endpoint_url(endpoint).force_path_style(true);
+    }
+    let mut dynamodb_config = DynamoDbConfigBuilder::from(config);
+    if let Ok(endpoint) = var("DYNAMODB_ENDPOINT") {
+        dynamodb_config = dynamodb_config.endpoint_url(endpoint);
+    }
+
+    let s3 = S3Client::from_conf(s3_config.build());
+    let dynamodb = DynamoDbClient::from_conf(dynamodb_config.build());
+    let ledger = DynamoDbDao::new(dynamodb.clone(), var("INGESTIONS_TABLE")?);
+    let mut loader_config = LoaderConfig::from_env()?;
+    loader_config.reload = options.reload;
+
+    let summary = run_backfill(
+        &s3,
+        &dynamodb,
+        &ledger,
+        &SyncFormats::with_defaults()?,
+        &loader_config,
+        &options,
+    )
+    .await?;
+    println!("{}", to_string(&summary)?);
+
+    if !summary.failed.is_empty() {
+        exit(1);
+    }
+
+    Ok(())
+}
@@ -0,0 +1,39 @@
+/*
+ * This file is part of the IVMS Online.
+ *
+ * @copyright 2024 © by Rafał Wrzeszcz - Wrzasq.pl.
+ */
+
+use env_logger::Env;
+use ivms_reports_aggregator::event_filter::EventFilters;
+use ivms_reports_aggregator::inspect::{inspect_file, InspectOptions};
+use ivms_reports_aggregator::loader::archive_limits_from_env;
+use ivms_reports_aggregator::parsers::SyncFormats;
+use ivms_reports_aggregator::timezone::VesselTimezones;
+use lambda_runtime::Error;
+use std::env::args;
+use std::process::exit;
+use tokio::main as tokio_main;
+
+#[tokio_main]
+async fn main() -> Result<(), Error> {
+    // parser warnings are part of the output, so only failures are logged
+    env_logger::Builder::from_env(Env::default().default_filter_or("error")).init();
+
+    let options = InspectOptions::from_args(args().skip(1))?;
+    let inspection = inspect_file(
+        &SyncFormats::with_defaults()?,
+        &archive_limits_from_env()?,
+        &EventFilters::from_env()?,
+        &VesselTimezones::from_env()?,
+        &options,
+    )
+    .await?;
+    println!("{}", inspection.render(&options.output)?);
+
+    if inspection.failure.is_some() {
+        exit(1);
+    }
+
+    Ok(())
+}
@@ -0,0 +1,157 @@
+/*
+ * This file is part of the IVMS Online.
+ *
+ * @copyright 2024 © by Rafał Wrzeszcz - Wrzasq.pl.
+ */
+
+use crate::runtime_error::RuntimeError;
+use glob::Pattern;
+use serde::Deserialize;
+use serde_json::from_str;
+use std::collections::HashMap;
+use std::env::{var, VarError};
+use std::sync::Arc;
+use uuid::Uuid;
+
+static GLOBAL_KEY: &str = "*";
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct EventFilterConfig {
+    allow: Option<Vec<String>>,
+    #[serde(default)]
+    deny: Vec<String>,
+}
+
+fn patterns_of(globs: Vec<String>) -> Result<Vec<Pattern>, RuntimeError> {
+    globs.iter().map(|glob| Ok(Pattern::new(glob)?)).collect()
+}
+
+#[derive(Default)]
+#[doc = "Selects series rows that are ingested, by their `event_text`."]
+pub struct EventFilter {
+    // no list means all events are allowed
+    allow: Option<Vec<Pattern>>,
+    deny: Vec<Pattern>,
+}
+
+impl EventFilter {
+    #[doc = "Event is accepted when it matches any allowed pattern (if they are listed) and none of denied ones."]
+    pub fn accepts(&self, event_text: &str) -> bool {
+        self.allow
+            .as_ref()
+            .is_none_or(|allow| allow.iter().any(|pattern| pattern.matches(event_text)))
+            && !self.deny.iter().any(|pattern| pattern.matches(event_text))
+    }
+}
+
+impl TryFrom<EventFilterConfig> for EventFilter {
+    type Error = RuntimeError;
+
+    fn try_from(config: EventFilterConfig) -> Result<Self, Self::Error> {
+        Ok(Self {
+            allow: config.allow.map(patterns_of).transpose()?,
+            deny: patterns_of(config.deny)?,
+        })
+    }
+}
+
+#[derive(Default)]
+#[doc = "Event filters of all customers."]
+pub struct EventFilters {
+    global: Arc<EventFilter>,
+    customers: HashMap<Uuid, Arc<EventFilter>>,
+}
+
+impl EventFilters {
+    #[doc = "Reads filters from `EVENT_FILTERS` variable, when it's not set all events are ingested."]
+    pub fn from_env() -> Result<Self, RuntimeError> {
+        match var("EVENT_FILTERS") {
+            Ok(json) => Self::from_json(json.as_str()),
+            Err(VarError::NotPresent) => Ok(Self::default()),
+            Err(error) => Err(error.into()),
+        }
+    }
+
+    #[doc = "Parses filters keyed by customer ID, `*` key holds filter of customers without own one."]
+    pub fn from_json(json: &str) -> Result<Self, RuntimeError> {
+        let mut filters = Self::default();
+
+        for (key, config) in from_str::<HashMap<String, EventFilterConfig>>(json)? {
+            let filter = Arc::new(EventFilter::try_from(config)?);
+
+            if key == GLOBAL_KEY {
+                filters.global = filter;
+            } else {
+                filters.customers.insert(Uuid::parse_str(key.as_str())?, filter);
+            }
+        }
+
+        Ok(filters)
+    }
+
+    #[doc = "Customer filter replaces the global one entirely, they are not combined."]
+    pub fn for_customer(&self, customer_id: &Uuid) -> Arc<EventFilter> {
+        self.customers.get(customer_id).unwrap_or(&self.global).clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::event_filter::{EventFilter, EventFilters};
+    use crate::runtime_error::RuntimeError;
+    use uuid::{uuid, Uuid};
+
+    const CUSTOMER_ID: Uuid = uuid!("00000000-0000-0000-0000-000000000001");
+    const OTHER_CUSTOMER_ID: Uuid = uuid!("00000000-0000-0000-0000-000000000002");
+
+    #[test]
+    fn accept_all_by_default() {
+        let filter = EventFilter::default();
+
+        assert!(filter.accepts("daily"));
+        assert!(filter.accepts(""));
+    }
+
+    #[test]
+    fn allow_and_deny() {
+        let filters = EventFilters::from_json(
+            r#"{"*": {"allow": ["daily", "noon*"], "deny": ["noon-test"]}, "00000000-0000-0000-0000-000000000001": {"deny": ["debug*"]}}"#,
+        )
+        .unwrap();
+
+        let global = filters.for_customer(&OTHER_CUSTOMER_ID);
+        assert!(global.accepts("daily"));
+        assert!(global.accepts("noon-report"));
+        assert!(!global.accepts("noon-test"));
+        assert!(!global.accepts("debug-dump"));
+
+        let customer = filters.for_customer(&CUSTOMER_ID);
+        assert!(customer.accepts("weekly"));
+        assert!(customer.accepts("noon-test"));
+        assert!(!customer.accepts("debug-dump"));
+    }
+
+    #[test]
+    fn empty_allow_list() {
+        let filters = EventFilters::from_json(r#"{"*": {"allow": []}}"#).unwrap();
+
+        assert!(!filters.for_customer(&CUSTOMER_ID).accepts("daily"));
+    }
+
+    #[test]
+    fn invalid_filters() {
+        assert!(matches!(
+            EventFilters::from_json(r#"{"*": {"allow": ["[a-"]}}"#),
+            Err(RuntimeError::PatternError(_))
+        ));
+        assert!(matches!(
+            EventFilters::from_json(r#"{"customer": {"deny": []}}"#),
+            Err(RuntimeError::UuidError(_))
+        ));
+        assert!(matches!(
+            EventFilters::from_json(r#"{"*": {"ignore": []}}"#),
+            Err(RuntimeError::SerializationError(_))
+        ));
+    }
+}
@@ -0,0 +1,425 @@
+/*
+ * This file is part of the IVMS Online.
+ *
+ * @copyright 2024 © by Rafał Wrzeszcz - Wrzasq.pl.
+ */
+
+use crate::runtime_error::RuntimeError;
+use aws_lambda_events::eventbridge::EventBridgeEvent;
+use aws_lambda_events::s3::S3EventRecord;
+use aws_lambda_events::sns::{SnsMessage, SnsRecord};
+use aws_lambda_events::sqs::{BatchItemFailure, SqsBatchResponse, SqsEvent, SqsMessage};
+use log::error;
+use serde::{Deserialize, Serialize};
+use serde_json::{from_str, from_value, Value};
+use std::future::Future;
+use urlencoding::decode;
+
+#[derive(Debug, PartialEq)]
+#[doc = "S3 object that triggered the loader."]
+pub struct ObjectRef {
+    pub bucket_name: String,
+    pub object_key: String,
+    pub event: ObjectEvent,
+}
+
+#[derive(Debug, PartialEq)]
+#[doc = "Change of the S3 object."]
+pub enum ObjectEvent {
+    Created,
+    #[doc = "Version is only set when specific version was permanently deleted (not for delete markers)."]
+    Removed {
+        version_id: Option<String>,
+    },
+}
+
+#[derive(Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+struct ObjectEventDetail {
+    bucket: ObjectEventBucket,
+    object: ObjectEventObject,
+    deletion_type: Option<String>,
+}
+
+#[derive(Deserialize, Serialize)]
+struct ObjectEventBucket {
+    name: String,
+}
+
+#[derive(Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+struct ObjectEventObject {
+    key: String,
+    version_id: Option<String>,
+}
+
+#[doc = "Extracts S3 objects from any supported delivery envelope: direct S3 notification, SNS, SQS (with raw or SNS \
+notification body) and EventBridge."]
+pub fn objects_of(event: Value) -> Result<Vec<ObjectRef>, RuntimeError> {
+    if let Some(Value::Array(records)) = event.get("Records") {
+        let mut objects = vec![];
+        for record in records {
+            objects.append(&mut objects_of_record(record.clone())?);
+        }
+        Ok(objects)
+    } else if event.get("detail-type").is_some() {
+        objects_of_event_bridge(from_value(event)?)
+    } else if event.get("Type").and_then(Value::as_str) == Some("Notification") {
+        // SNS notification delivered to SQS without raw message delivery
+        objects_of(from_str(from_value::<SnsMessage>(event)?.message.as_str())?)
+    } else if event.get("Event").and_then(Value::as_str) == Some("s3:TestEvent") {
+        // sent by S3 when notification configuration is created
+        Ok(vec![])
+    } else {
+        Err(RuntimeError::MalformedS3Event)
+    }
+}
+
+fn objects_of_record(record: Value) -> Result<Vec<ObjectRef>, RuntimeError> {
+    // SNS records use PascalCase, contrary to all other sources
+    let source = record
+        .get("eventSource")
+        .or_else(|| record.get("EventSource"))
+        .and_then(Value::as_str);
+
+    match source {
+        Some("aws:s3") => object_of_s3_record(from_value(record)?).map(|object| vec![object]),
+        Some("aws:sns") => objects_of(from_str(from_value::<SnsRecord>(record)?.sns.message.as_str())?),
+        Some("aws:sqs") => objects_of(from_str(
+            from_value::<SqsMessage>(record)?
+                .body
+                .ok_or(RuntimeError::MalformedS3Event)?
+                .as_str(),
+        )?),
+        _ => Err(RuntimeError::MalformedS3Event),
+    }
+}
+
+fn object_of_s3_record(record: S3EventRecord) -> Result<ObjectRef, RuntimeError> {
+    let event = match record.event_name.as_deref().unwrap_or_default() {
+        "ObjectRemoved:Delete" => ObjectEvent::Removed {
+            version_id: record.s3.object.version_id,
+        },
+        // delete marker hides the object, but its version ID doesn't identify any loaded version
+        name if name.starts_with("ObjectRemoved:") => ObjectEvent::Removed { version_id: None },
+        _ => ObjectEvent::Created,
+    };
+
+    Ok(ObjectRef {
+        bucket_name: record.s3.bucket.name.ok_or(RuntimeError::MalformedS3Event)?,
+        // notifications contain URL-encoded keys
+        object_key: decode(record.s3.object.key.ok_or(RuntimeError::MalformedS3Event)?.as_str())
+            .map_err(|_| RuntimeError::MalformedS3Event)?
+            .into_owned(),
+        event,
+    })
+}
+
+fn objects_of_event_bridge(event: EventBridgeEvent<Value>) -> Result<Vec<ObjectRef>, RuntimeError> {
+    if event.source != "aws.s3" {
+        return Err(RuntimeError::MalformedS3Event);
+    }
+
+    // other S3 events may be routed by too broad rule, they are not relevant for loading
+    if event.detail_type != "Object Created" && event.detail_type != "Object Deleted" {
+        return Ok(vec![]);
+    }
+
+    // contrary to S3 notifications, EventBridge events contain plain keys
+    let detail = from_value::<ObjectEventDetail>(event.detail)?;
+    let event = match (event.detail_type.as_str(), detail.deletion_type.as_deref()) {
+        ("Object Created", _) => ObjectEvent::Created,
+        ("Object Deleted", Some("Permanently Deleted")) => ObjectEvent::Removed {
+            version_id: detail.object.version_id,
+        },
+        _ => ObjectEvent::Removed { version_id: None },
+    };
+
+    Ok(vec![ObjectRef {
+        bucket_name: detail.bucket.name,
+        object_key: detail.object.key,
+        event,
+    }])
+}
+
+#[doc = "Loads objects of every queue message independently, so that only failed messages are returned for redelivery."]
+pub async fn process_queue_batch<F, R, T>(event: SqsEvent, load: F) -> SqsBatchResponse
+where
+    F: Fn(ObjectRef) -> R,
+    R: Future<Output = Result<T, RuntimeError>>,
+{
+    let mut batch_item_failures = vec![];
+
+    for message in event.records {
+        let message_id = message.message_id.unwrap_or_default();
+
+        if let Err(error) = process_message(message.body, &load).await {
+            error!("Failed to process message {}: {}.", message_id, error);
+            batch_item_failures.push(BatchItemFailure {
+                item_identifier: message_id,
+            });
+        }
+    }
+
+    SqsBatchResponse { batch_item_failures }
+}
+
+// single message may carry multiple objects, all of them are retried as loading is idempotent
+async fn process_message<F, R, T>(body: Option<String>, load: &F) -> Result<(), RuntimeError>
+where
+    F: Fn(ObjectRef) -> R,
+    R: Future<Output = Result<T, RuntimeError>>,
+{
+    for object in objects_of(from_str(body.ok_or(RuntimeError::MalformedS3Event)?.as_str())?)? {
+        load(object).await?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::events::{objects_of, process_queue_batch, ObjectEvent, ObjectRef};
+    use crate::runtime_error::RuntimeError;
+    use aws_lambda_events::sqs::SqsEvent;
+    use serde_json::{from_value, json, Value};
+    use std::cell::RefCell;
+
+    fn s3_event() -> Value {
+        json!({
+            "Records": [
+                {
+                    "eventVersion": "2.1",
+                    "eventSource": "aws:s3",
+                    "awsRegion": "eu-central-1",
+                    "eventTime": "2024-01-05T12:00:00.000Z",
+                    "eventName": "ObjectCreated:Put",
+                    "userIdentity": {"principalId": "AWS:test"},
+                    "requestParameters": {"sourceIPAddress": "127.0.0.1"},
+                    "responseElements": {},
+                    "s3": {
+                        "s3SchemaVersion": "1.0",
+                        "configurationId": "test",
+                        "bucket": {
+                            "name": "upload",
+                            "ownerIdentity": {"principalId": "test"},
+                            "arn": "arn:aws:s3:::upload"
+                        },
+                        "object": {
+                            "key": "v1/SYNC/test%20sync.zip",
+                            "size": 1024,
+                            "eTag": "abc",
+                            "sequencer": "0"
+                        }
+                    }
+                }
+            ]
+        })
+    }
+
+    fn sns_event(message: &Value) -> Value {
+        json!({
+            "Type": "Notification",
+            "MessageId": "00000000-0000-0000-0000-000000000000",
+            "TopicArn": "arn:aws:sns:eu-central-1:123456789012:uploads",
+            "Subject": null,
+            "Message": message.to_string(),
+            "Timestamp": "2024-01-05T12:00:00.000Z",
+            "SignatureVersion": "1",
+            "Signature": "",
+            "SigningCertUrl": "",
+            "UnsubscribeUrl": "",
+            "MessageAttributes": {}
+        })
+    }
+
+    fn sqs_event(body: &Value) -> Value {
+        json!({
+            "Records": [
+                {
+                    "messageId": "00000000-0000-0000-0000-000000000000",
+                    "receiptHandle": "test",
+                    "body": body.to_string(),
+                    "attributes": {},
+                    "messageAttributes": {},
+                    "md5OfBody": "",
+                    "eventSource": "aws:sqs",
+                    "eventSourceARN": "arn:aws:sqs:eu-central-1:123456789012:uploads",
+                    "awsRegion": "eu-central-1"
+                }
+            ]
+        })
+    }
+
+    fn expected() -> Vec<ObjectRef> {
+        vec![ObjectRef {
+            bucket_name: "upload".into(),
+            object_key: "v1/SYNC/test sync.zip".into(),
+            event: ObjectEvent::Created,
+        }]
+    }
+
+    #[test]
+    fn direct_s3_event() {
+        assert_eq!(expected(), objects_of(s3_event()).unwrap());
+    }
+
+    #[test]
+    fn sns_wrapped_event() {
+        let event = json!({
+            "Records": [
+                {
+                    "EventSource": "aws:sns",
+                    "EventVersion": "1.0",
+                    "EventSubscriptionArn": "arn:aws:sns:eu-central-1:123456789012:uploads:test",
+                    "Sns": sns_event(&s3_event())
+                }
+            ]
+        });
+
+        assert_eq!(expected(), objects_of(event).unwrap());
+    }
+
+    #[test]
+    fn sqs_wrapped_event() {
+        assert_eq!(expected(), objects_of(sqs_event(&s3_event())).unwrap());
+    }
+
+    #[test]
+    fn sqs_wrapped_sns_event() {
+        assert_eq!(expected(), objects_of(sqs_event(&sns_event(&s3_event()))).unwrap());
+    }
+
+    #[test]
+    fn event_bridge_event() {
+        let event = json!({
+            "version": "0",
+            "id": "00000000-0000-0000-0000-000000000000",
+            "detail-type": "Object Created",
+            "source": "aws.s3",
+            "account": "123456789012",
+            "time": "2024-01-05T12:00:00Z",
+            "region": "eu-central-1",
+            "resources": ["arn:aws:s3:::upload"],
+            "detail": {
+                "version": "0",
+                "bucket": {"name": "upload"},
+                "object": {"key": "v1/SYNC/test sync.zip", "size": 1024, "etag": "abc"},
+                "reason": "PutObject"
+            }
+        });
+
+        assert_eq!(expected(), objects_of(event).unwrap());
+    }
+
+    #[test]
+    fn removal_events() {
+        let mut event = s3_event();
+        event["Records"][0]["eventName"] = json!("ObjectRemoved:Delete");
+        event["Records"][0]["s3"]["object"]["versionId"] = json!("v2");
+        assert_eq!(
+            ObjectEvent::Removed {
+                version_id: Some("v2".into())
+            },
+            objects_of(event.clone()).unwrap().remove(0).event
+        );
+
+        event["Records"][0]["eventName"] = json!("ObjectRemoved:DeleteMarkerCreated");
+        assert_eq!(
+            ObjectEvent::Removed { version_id: None },
+            objects_of(event).unwrap().remove(0).event
+        );
+
+        let event = json!({
+            "detail-type": "Object Deleted",
+            "source": "aws.s3",
+            "detail": {
+                "bucket": {"name": "upload"},
+                "object": {"key": "v1/SYNC/test sync.zip", "version-id": "v2"},
+                "deletion-type": "Permanently Deleted"
+            }
+        });
+        assert_eq!(
+            vec![ObjectRef {
+                bucket_name: "upload".into(),
+                object_key: "v1/SYNC/test sync.zip".into(),
+                event: ObjectEvent::Removed {
+                    version_id: Some("v2".into())
+                },
+            }],
+            objects_of(event).unwrap()
+        );
+    }
+
+    #[test]
+    fn irrelevant_events() {
+        assert!(objects_of(json!({"Event": "s3:TestEvent", "Bucket": "upload"}))
+            .unwrap()
+            .is_empty());
+        assert!(objects_of(json!({
+            "detail-type": "Object Restore Completed",
+            "source": "aws.s3",
+            "detail": {}
+        }))
+        .unwrap()
+        .is_empty());
+    }
+
+    #[test]
+    fn unknown_events() {
+        assert!(matches!(
+            objects_of(json!({"customerId": "test"})),
+            Err(RuntimeError::MalformedS3Event)
+        ));
+        assert!(matches!(
+            objects_of(json!({"Records": [{"eventSource": "aws:dynamodb"}]})),
+            Err(RuntimeError::MalformedS3Event)
+        ));
+        assert!(matches!(
+            objects_of(json!({"detail-type": "Object Created", "source": "aws.ec2", "detail": {}})),
+            Err(RuntimeError::MalformedS3Event)
+        ));
+    }
+
+    #[tokio::test]
+    async fn mixed_queue_batch() {
+        let mut event = sqs_event(&s3_event());
+        let records = event["Records"].as_array_mut().unwrap();
+        let template = records[0].clone();
+        for (message_id, body) in [
+            ("failing", s3_event().to_string().replace("test%20sync.zip", "fail.zip")),
+            ("malformed", "{".into()),
+            ("last", s3_event().to_string().replace("test%20sync.zip", "last.zip")),
+        ] {
+            let mut record = template.clone();
+            record["messageId"] = json!(message_id);
+            record["body"] = json!(body);
+            records.push(record);
+        }
+
+        let loaded = RefCell::new(vec![]);
+        let response = process_queue_batch(from_value::<SqsEvent>(event).unwrap(), |object| {
+            let result = match object.object_key.as_str() {
+                "v1/SYNC/fail.zip" => Err(RuntimeError::UnrecognizedInput),
+                _ => Ok(()),
+            };
+            loaded.borrow_mut().push(object.object_key);
+            async { result }
+        })
+        .await;
+
+        // records after failed ones are still processed
+        assert_eq!(
+            vec!["v1/SYNC/test sync.zip", "v1/SYNC/fail.zip", "v1/SYNC/last.zip"],
+            loaded.take()
+        );
+        assert_eq!(
+            vec!["failing", "malformed"],
+            response
+                .batch_item_failures
+                .iter()
+                .map(|failure| failure.item_identifier.as_str())
+                .collect::<Vec<_>>()
+        );
+    }
+}
@@ -0,0 +1,117 @@
+/*
+ * This file is part of the IVMS Online.
+ *
+ * @copyright 2024 © by Rafał Wrzeszcz - Wrzasq.pl.
+ */
+
+use crate::runtime_error::RuntimeError;
+use futures::io::{Chain, Cursor};
+use futures::{AsyncRead, AsyncReadExt};
+use lazy_regex::{regex_is_match, regex_replace};
+
+static HEAD_SIZE: u64 = 512;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[doc = "Container format of uploaded sync data."]
+pub enum InputFormat {
+    Zip,
+    TarGzip,
+    #[doc = "Single gzip-compressed JSON document."]
+    Gzip,
+    #[doc = "Single plain JSON document."]
+    Json,
+}
+
+#[doc = "Archive entry description, common for all input formats."]
+pub struct EntryInfo {
+    pub name: String,
+    #[doc = "Whether entry is a regular file that can be parsed."]
+    pub regular: bool,
+    #[doc = "Compressed size, for formats compressed as a whole it's size of the entire upload."]
+    pub compressed_size: u64,
+    #[doc = "Uncompressed size declared by the format, `0` if unknown."]
+    pub declared_size: u64,
+}
+
+#[doc = "Reads beginning of the stream for format detection. Returns read bytes and stream rewound to its beginning."]
+pub async fn peek<R: AsyncRead + Unpin>(mut reader: R) -> Result<(Vec<u8>, Chain<Cursor<Vec<u8>>, R>), RuntimeError> {
+    let mut head = vec![];
+    (&mut reader).take(HEAD_SIZE).read_to_end(&mut head).await?;
+
+    Ok((head.clone(), Cursor::new(head).chain(reader)))
+}
+
+#[doc = "Detects input format by content signature, extension resolves content of gzip stream."]
+pub fn detect_format(object_key: &str, head: &[u8]) -> Option<InputFormat> {
+    if head.starts_with(b"PK\x03\x04") || head.starts_with(b"PK\x05\x06") {
+        Some(InputFormat::Zip)
+    } else if head.starts_with(b"\x1f\x8b") {
+        match regex_is_match!(r"(?i)\.(tar\.gz|tgz)$", object_key) {
+            true => Some(InputFormat::TarGzip),
+            false => Some(InputFormat::Gzip),
+        }
+    } else {
+        match head.iter().find(|byte| !byte.is_ascii_whitespace()) {
+            Some(b'{' | b'[') => Some(InputFormat::Json),
+            _ => None,
+        }
+    }
+}
+
+#[doc = "Single-document uploads are handled as `data/{file name}` archive entry (without `.gz` extension)."]
+pub fn entry_name_of(object_key: &str) -> String {
+    let filename = object_key.rsplit('/').next().unwrap_or_default();
+
+    format!("data/{}", regex_replace!(r"(?i)\.gz$", filename, ""))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::input::{detect_format, entry_name_of, peek, InputFormat};
+    use futures::AsyncReadExt;
+
+    static ZIP: &[u8] = include_bytes!("../tests/fixtures/sync.zip");
+    static TAR_GZIP: &[u8] = include_bytes!("../tests/fixtures/sync.tar.gz");
+    static GZIP: &[u8] = include_bytes!("../tests/fixtures/reports.json.gz");
+    static JSON: &[u8] = include_bytes!("../tests/fixtures/reports.json");
+
+    #[test]
+    fn detect_fixtures() {
+        assert_eq!(Some(InputFormat::Zip), detect_format("v1/SYNC/sync.zip", ZIP));
+        assert_eq!(
+            Some(InputFormat::TarGzip),
+            detect_format("v1/SYNC/sync.tar.gz", TAR_GZIP)
+        );
+        assert_eq!(Some(InputFormat::TarGzip), detect_format("v1/SYNC/sync.TGZ", TAR_GZIP));
+        assert_eq!(Some(InputFormat::Gzip), detect_format("v1/SYNC/reports.json.gz", GZIP));
+        assert_eq!(Some(InputFormat::Json), detect_format("v1/SYNC/reports.json", JSON));
+    }
+
+    #[test]
+    fn detect_by_content() {
+        // content signature wins over misleading extension
+        assert_eq!(Some(InputFormat::Zip), detect_format("v1/SYNC/reports.json", ZIP));
+        assert_eq!(
+            Some(InputFormat::Json),
+            detect_format("v1/SYNC/sync.zip", b"\n  [1, 2]")
+        );
+        assert!(detect_format("v1/SYNC/sync.zip", b"Rar!\x1a\x07").is_none());
+        assert!(detect_format("v1/SYNC/sync.zip", b"").is_none());
+    }
+
+    #[test]
+    fn single_entry_name() {
+        assert_eq!("data/reports.json", entry_name_of("v1/SYNC/a/b/reports.json.gz"));
+        assert_eq!("data/reports.json", entry_name_of("v1/SYNC/a/b/reports.json"));
+    }
+
+    #[tokio::test]
+    async fn peek_stream() {
+        let (head, mut stream) = peek(JSON).await.unwrap();
+        let mut data = vec![];
+        stream.read_to_end(&mut data).await.unwrap();
+
+        assert_eq!(&JSON[..512], head.as_slice());
+        assert_eq!(JSON, data.as_slice());
+    }
+}
@@ -0,0 +1,306 @@
+/*
+ * This file is part of the IVMS Online.
+ *
+ * @copyright 2024 © by Rafał Wrzeszcz - Wrzasq.pl.
+ */
+
+use crate::event_filter::EventFilters;
+use crate::limits::ArchiveLimits;
+use crate::loader::parse_archive;
+use crate::parsers::{EntryContext, ObjectSource, ParsedEntry, SyncFormats};
+use crate::runtime_error::RuntimeError;
+use crate::timezone::VesselTimezones;
+use aws_sdk_s3::primitives::ByteStream;
+use chrono::Utc;
+use serde::Serialize;
+use serde_json::to_string;
+use std::fs::read;
+use std::path::PathBuf;
+use std::sync::Arc;
+use uuid::Uuid;
+
+static DEFAULT_FORMAT: &str = "v1";
+
+#[derive(Debug, PartialEq)]
+#[doc = "Output layout of inspection result."]
+pub enum OutputFormat {
+    Json,
+    Table,
+}
+
+#[derive(Debug, PartialEq)]
+#[doc = "Options of local archive inspection."]
+pub struct InspectOptions {
+    #[doc = "Local sync archive (or single entry file)."]
+    pub path: PathBuf,
+    #[doc = "Sync format prefix, selecting entry parsers."]
+    pub format: String,
+    pub output: OutputFormat,
+    #[doc = "Customer ID put into records, as it would be taken from the object key."]
+    pub customer_id: Uuid,
+    #[doc = "Vessel ID put into records, as it would be taken from the object key."]
+    pub vessel_id: Uuid,
+}
+
+impl InspectOptions {
+    #[doc = "Parses command line arguments (without program name)."]
+    pub fn from_args(args: impl IntoIterator<Item = String>) -> Result<Self, RuntimeError> {
+        let mut path = None;
+        let mut options = Self {
+            path: PathBuf::new(),
+            format: DEFAULT_FORMAT.into(),
+            output: OutputFormat::Table,
+            customer_id: Uuid::nil(),
+            vessel_id: Uuid::nil(),
+        };
+
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            let mut value = || {
+                args.next()
+                    .ok_or_else(|| RuntimeError::InvalidArguments(format!("missing value of {arg}")))
+            };
+
+            match arg.as_str() {
+                "--format" => options.format = value()?,
+                "--output" => {
+                    options.output = match value()?.as_str() {
+                        "json" => OutputFormat::Json,
+                        "table" => OutputFormat::Table,
+                        output => return Err(RuntimeError::InvalidArguments(format!("unknown output {output}"))),
+                    }
+                }
+                "--customer" => options.customer_id = Uuid::parse_str(value()?.as_str())?,
+                "--vessel" => options.vessel_id = Uuid::parse_str(value()?.as_str())?,
+                _ if arg.starts_with("--") => {
+                    return Err(RuntimeError::InvalidArguments(format!("unknown option {arg}")))
+                }
+                _ if path.is_none() => path = Some(arg.into()),
+                _ => return Err(RuntimeError::InvalidArguments(format!("unexpected argument {arg}"))),
+            }
+        }
+
+        options.path = path.ok_or(RuntimeError::InvalidArguments("file path is required".into()))?;
+
+        Ok(options)
+    }
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+#[doc = "Records that would be loaded from the archive."]
+pub struct Inspection {
+    #[serde(flatten)]
+    pub parsed: ParsedEntry,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[doc = "Entry that was being read when the archive turned out to be malformed."]
+    pub failed_entry: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[doc = "Reason for which the loader would quarantine the archive."]
+    pub failure: Option<String>,
+}
+
+impl Inspection {
+    #[doc = "Renders the result in requested layout."]
+    pub fn render(&self, output: &OutputFormat) -> Result<String, RuntimeError> {
+        match output {
+            OutputFormat::Json => Ok(to_string(self)?),
+            OutputFormat::Table => self.table(),
+        }
+    }
+
+    fn table(&self) -> Result<String, RuntimeError> {
+        let mut rows = vec![["REPORT", "FIELD", "LABEL", "VALUE", "RECORDED AT"].map(String::from)];
+        for report in &self.parsed.reports {
+            rows.push([
+                report.report_name.clone(),
+                report.field_name.clone(),
+                report.label.clone(),
+                to_string(&report.value)?,
+                report
+                    .source
+                    .as_ref()
+                    .map(|source| source.recorded_at.to_rfc3339())
+                    .unwrap_or_default(),
+            ]);
+        }
+
+        let mut widths = [0; 5];
+        for row in &rows {
+            for (width, cell) in widths.iter_mut().zip(row) {
+                *width = (*width).max(cell.chars().count());
+            }
+        }
+
+        let mut lines = rows
+            .iter()
+            .map(|row| {
+                row.iter()
+                    .zip(widths)
+                    .map(|(cell, width)| format!("{cell:width$}"))
+                    .collect::<Vec<String>>()
+                    .join("  ")
+                    .trim_end()
+                    .to_string()
+            })
+            .collect::<Vec<String>>();
+
+        lines.push(format!(
+            "\n{} records, {} skipped, {} rows filtered.",
+            self.parsed.reports.len(),
+            self.parsed.skipped.len(),
+            self.parsed.filtered
+        ));
+        lines.extend(self.parsed.skipped.iter().map(|reason| format!("skipped: {reason}")));
+        lines.extend(self.parsed.warnings.iter().map(|message| format!("warning: {message}")));
+        if let Some(failure) = &self.failure {
+            lines.push(format!(
+                "failed at entry {}: {}",
+                self.failed_entry.as_deref().unwrap_or("-"),
+                failure
+            ));
+        }
+
+        Ok(lines.join("\n"))
+    }
+}
+
+#[doc = "Parses local file with the same parsers, limits, filters and timezones as the loader, without any AWS access."]
+pub async fn inspect_file(
+    formats: &SyncFormats,
+    limits: &ArchiveLimits,
+    event_filters: &EventFilters,
+    vessel_timezones: &VesselTimezones,
+    options: &InspectOptions,
+) -> Result<Inspection, RuntimeError> {
+    let parsers = formats
+        .find(options.format.as_str())
+        .ok_or_else(|| RuntimeError::InvalidArguments(format!("unknown sync format {}", options.format)))?;
+    let data = read(&options.path)?;
+    let size = data.len() as u64;
+    let context = EntryContext {
+        customer_id: options.customer_id,
+        vessel_id: options.vessel_id,
+        object: ObjectSource {
+            bucket_name: String::new(),
+            // file name drives input format detection, same as object key
+            object_key: options.path.to_string_lossy().into(),
+            version_id: None,
+            ingested_at: Utc::now(),
+        },
+        entry_path: String::new(),
+        events: event_filters.for_customer(&options.customer_id),
+        // sensor catalog is not available offline, so only units embedded in labels are recognized
+        units: Arc::default(),
+        timezone: vessel_timezones.for_vessel(&options.vessel_id),
+    };
+
+    let (result, failed_entry, parsed) = parse_archive(parsers, limits, context, ByteStream::from(data), size).await;
+
+    Ok(Inspection {
+        parsed,
+        failed_entry,
+        failure: result.err().map(|error| error.to_string()),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::event_filter::EventFilters;
+    use crate::inspect::{inspect_file, InspectOptions, OutputFormat};
+    use crate::limits::ArchiveLimits;
+    use crate::parsers::SyncFormats;
+    use crate::runtime_error::RuntimeError;
+    use crate::timezone::VesselTimezones;
+    use serde_json::{from_str, Value};
+    use uuid::{uuid, Uuid};
+
+    static LIMITS: ArchiveLimits = ArchiveLimits {
+        max_total_size: 1024 * 1024,
+        max_entry_size: 1024 * 1024,
+        max_entries: 10,
+        max_compression_ratio: 100,
+    };
+
+    fn args(args: &str) -> Result<InspectOptions, RuntimeError> {
+        InspectOptions::from_args(args.split_whitespace().map(String::from))
+    }
+
+    #[test]
+    fn parse_args() {
+        assert_eq!(
+            InspectOptions {
+                path: "sync.zip".into(),
+                format: "v2".into(),
+                output: OutputFormat::Json,
+                customer_id: uuid!("00000000-0000-0000-0000-000000000001"),
+                vessel_id: uuid!("00000000-0000-0000-0000-000000000002"),
+            },
+            args("--format v2 sync.zip --output json --customer 00000000-0000-0000-0000-000000000001 --vessel 00000000-0000-0000-0000-000000000002")
+                .unwrap()
+        );
+
+        let defaults = args("sync.zip").unwrap();
+        assert_eq!("v1", defaults.format);
+        assert_eq!(OutputFormat::Table, defaults.output);
+        assert_eq!(Uuid::nil(), defaults.customer_id);
+    }
+
+    #[test]
+    fn parse_invalid_args() {
+        assert!(matches!(args(""), Err(RuntimeError::InvalidArguments(_))));
+        assert!(matches!(args("a.zip b.zip"), Err(RuntimeError::InvalidArguments(_))));
+        assert!(matches!(
+            args("a.zip --output xml"),
+            Err(RuntimeError::InvalidArguments(_))
+        ));
+        assert!(matches!(
+            args("a.zip --dry-run"),
+            Err(RuntimeError::InvalidArguments(_))
+        ));
+        assert!(matches!(args("a.zip --vessel x"), Err(RuntimeError::UuidError(_))));
+    }
+
+    #[tokio::test]
+    async fn inspect_archive() {
+        let options = args("tests/fixtures/sync.zip").unwrap();
+        let inspection = inspect_file(
+            &SyncFormats::with_defaults().unwrap(),
+            &LIMITS,
+            &EventFilters::default(),
+            &VesselTimezones::default(),
+            &options,
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(3, inspection.parsed.reports.len());
+        assert!(inspection.failure.is_none());
+
+        let json = from_str::<Value>(inspection.render(&OutputFormat::Json).unwrap().as_str()).unwrap();
+        assert_eq!(3, json["reports"].as_array().unwrap().len());
+        assert_eq!("2024-01-05.daily", json["reports"][0]["reportName"]);
+        assert!(json.get("failure").is_none());
+
+        let table = inspection.render(&OutputFormat::Table).unwrap();
+        assert!(table.starts_with("REPORT "));
+        assert!(table.contains("\n3 records, 0 skipped, 0 rows filtered."));
+    }
+
+    #[tokio::test]
+    async fn inspect_unknown_format() {
+        let options = args("tests/fixtures/sync.zip --format v3").unwrap();
+
+        assert!(matches!(
+            inspect_file(
+                &SyncFormats::with_defaults().unwrap(),
+                &LIMITS,
+                &EventFilters::default(),
+                &VesselTimezones::default(),
+                &options
+            )
+            .await,
+            Err(RuntimeError::InvalidArguments(_))
+        ));
+    }
+}
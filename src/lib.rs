@@ -0,0 +1,27 @@
+/*
+ * This file is part of the IVMS Online.
+ *
+ * @copyright 2024 © by Rafał Wrzeszcz - Wrzasq.pl.
+ */
+
+#![cfg_attr(test, feature(future_join))]
+
+pub mod api;
+pub mod backfill;
+pub mod event_filter;
+pub mod events;
+mod input;
+pub mod inspect;
+mod limits;
+pub mod loader;
+pub mod migration;
+pub mod model;
+pub mod parsers;
+mod quarantine;
+pub mod report_dao;
+mod retraction;
+pub mod runtime_error;
+pub mod sensors;
+mod tar;
+pub mod timezone;
+pub mod units;
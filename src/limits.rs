@@ -0,0 +1,218 @@
+/*
+ * This file is part of the IVMS Online.
+ *
+ * @copyright 2024 © by Rafał Wrzeszcz - Wrzasq.pl.
+ */
+
+use crate::input::EntryInfo;
+use crate::runtime_error::RuntimeError;
+use futures::{AsyncRead, AsyncReadExt};
+use lazy_regex::regex_is_match;
+
+static ENTRY_CHUNK_SIZE: usize = 64 * 1024;
+
+// signatures of archive formats that are not expected inside sync archive
+static ARCHIVE_SIGNATURES: [&[u8]; 6] = [
+    b"PK\x03\x04",
+    b"\x1f\x8b",
+    b"BZh",
+    b"\xfd7zXZ\x00",
+    b"7z\xbc\xaf\x27\x1c",
+    b"Rar!\x1a\x07",
+];
+
+#[doc = "Safety caps for processed archives."]
+pub struct ArchiveLimits {
+    #[doc = "Maximum number of uncompressed bytes of all entries."]
+    pub max_total_size: u64,
+    #[doc = "Maximum number of uncompressed bytes of single entry."]
+    pub max_entry_size: u64,
+    #[doc = "Maximum number of entries."]
+    pub max_entries: usize,
+    #[doc = "Maximum ratio of uncompressed to compressed entry size."]
+    pub max_compression_ratio: u64,
+}
+
+#[doc = "Tracks archive reading against safety limits. Sizes declared in headers can't be trusted, so actually read bytes \
+are counted."]
+pub struct ArchiveGuard<'a> {
+    limits: &'a ArchiveLimits,
+    entries: usize,
+    total_size: u64,
+    entry_name: String,
+    entry_size: u64,
+    entry_compressed_size: u64,
+}
+
+impl<'a> ArchiveGuard<'a> {
+    pub fn new(limits: &'a ArchiveLimits) -> Self {
+        Self {
+            limits,
+            entries: 0,
+            total_size: 0,
+            entry_name: String::new(),
+            entry_size: 0,
+            entry_compressed_size: 0,
+        }
+    }
+
+    #[doc = "Validates next entry header, before any of its content is read."]
+    pub fn open_entry(&mut self, entry: &EntryInfo) -> Result<(), RuntimeError> {
+        self.entries += 1;
+        self.entry_name = entry.name.clone();
+        self.entry_size = 0;
+        self.entry_compressed_size = entry.compressed_size;
+
+        if self.entries > self.limits.max_entries {
+            return Err(RuntimeError::EntryCountLimitExceeded(self.limits.max_entries));
+        }
+
+        if regex_is_match!(r"(?i)\.(zip|jar|gz|tgz|tar|bz2|xz|7z|rar)$", &self.entry_name) {
+            return Err(RuntimeError::NestedArchive(self.entry_name.clone()));
+        }
+
+        // declared size is only used to reject entry early, actual size is verified while reading
+        if entry.declared_size > self.limits.max_entry_size {
+            return Err(RuntimeError::EntrySizeLimitExceeded(self.limits.max_entry_size));
+        }
+
+        Ok(())
+    }
+
+    #[doc = "Reads next chunk of current entry content. Returns empty chunk at the end of entry."]
+    pub async fn read_chunk(&mut self, entry: &mut (impl AsyncRead + Unpin)) -> Result<Vec<u8>, RuntimeError> {
+        let mut chunk = vec![0; ENTRY_CHUNK_SIZE];
+        let size = entry.read(&mut chunk).await?;
+        chunk.truncate(size);
+
+        self.consume(&chunk)?;
+
+        Ok(chunk)
+    }
+
+    fn consume(&mut self, chunk: &[u8]) -> Result<(), RuntimeError> {
+        if self.entry_size == 0 && ARCHIVE_SIGNATURES.iter().any(|signature| chunk.starts_with(signature)) {
+            return Err(RuntimeError::NestedArchive(self.entry_name.clone()));
+        }
+
+        self.entry_size += chunk.len() as u64;
+        self.total_size += chunk.len() as u64;
+
+        if self.entry_size > self.limits.max_entry_size {
+            Err(RuntimeError::EntrySizeLimitExceeded(self.limits.max_entry_size))
+        } else if self.total_size > self.limits.max_total_size {
+            Err(RuntimeError::ArchiveSizeLimitExceeded(self.limits.max_total_size))
+        } else if self.entry_size > self.entry_compressed_size.max(1) * self.limits.max_compression_ratio {
+            Err(RuntimeError::CompressionRatioLimitExceeded(
+                self.limits.max_compression_ratio,
+            ))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::input::EntryInfo;
+    use crate::limits::{ArchiveGuard, ArchiveLimits};
+    use crate::runtime_error::RuntimeError;
+
+    static LIMITS: ArchiveLimits = ArchiveLimits {
+        max_total_size: 100,
+        max_entry_size: 60,
+        max_entries: 2,
+        max_compression_ratio: 10,
+    };
+
+    fn guard(compressed_size: u64) -> ArchiveGuard<'static> {
+        let mut guard = ArchiveGuard::new(&LIMITS);
+        guard.entry_compressed_size = compressed_size;
+        guard
+    }
+
+    fn entry(filename: &str, declared_size: u64) -> EntryInfo {
+        EntryInfo {
+            name: filename.into(),
+            regular: true,
+            compressed_size: 10,
+            declared_size,
+        }
+    }
+
+    #[test]
+    fn open_entry() {
+        let mut guard = ArchiveGuard::new(&LIMITS);
+
+        assert!(guard.open_entry(&entry("data/reports.json", 50)).is_ok());
+        assert!(matches!(
+            guard.open_entry(&entry("data/inner.ZIP", 50)),
+            Err(RuntimeError::NestedArchive(name)) if name == "data/inner.ZIP"
+        ));
+        assert!(matches!(
+            guard.open_entry(&entry("data/events.json", 50)),
+            Err(RuntimeError::EntryCountLimitExceeded(2))
+        ));
+    }
+
+    #[test]
+    fn declared_entry_size_exceeded() {
+        assert!(matches!(
+            ArchiveGuard::new(&LIMITS).open_entry(&entry("data/reports.json", 61)),
+            Err(RuntimeError::EntrySizeLimitExceeded(60))
+        ));
+    }
+
+    #[test]
+    fn within_limits() {
+        let mut guard = guard(10);
+
+        assert!(guard.consume(&[b'{'; 50]).is_ok());
+        assert!(guard.consume(&[b' '; 10]).is_ok());
+    }
+
+    #[test]
+    fn entry_size_exceeded() {
+        let mut guard = guard(10);
+
+        assert!(guard.consume(&[b'{'; 50]).is_ok());
+        assert!(matches!(
+            guard.consume(&[b' '; 11]),
+            Err(RuntimeError::EntrySizeLimitExceeded(60))
+        ));
+    }
+
+    #[test]
+    fn total_size_exceeded() {
+        let mut guard = guard(10);
+
+        assert!(guard.consume(&[b'{'; 60]).is_ok());
+        guard.entry_size = 0;
+        assert!(matches!(
+            guard.consume(&[b'{'; 41]),
+            Err(RuntimeError::ArchiveSizeLimitExceeded(100))
+        ));
+    }
+
+    #[test]
+    fn compression_ratio_exceeded() {
+        let mut guard = guard(2);
+
+        assert!(matches!(
+            guard.consume(&[b'{'; 21]),
+            Err(RuntimeError::CompressionRatioLimitExceeded(10))
+        ));
+    }
+
+    #[test]
+    fn nested_archive_content() {
+        assert!(matches!(
+            guard(10).consume(b"PK\x03\x04rest"),
+            Err(RuntimeError::NestedArchive(_))
+        ));
+        // signature in the middle of the entry is just data
+        let mut guard = guard(10);
+        assert!(guard.consume(b"{").is_ok());
+        assert!(guard.consume(b"PK\x03\x04").is_ok());
+    }
+}
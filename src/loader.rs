@@ -4,240 +4,1139 @@
  * @copyright 2024 © by Rafał Wrzeszcz - Wrzasq.pl.
  */
 
-use crate::model::Report;
+use crate::event_filter::EventFilters;
+use crate::events::{ObjectEvent, ObjectRef};
+use crate::input::{detect_format, entry_name_of, peek, EntryInfo, InputFormat};
+use crate::limits::{ArchiveGuard, ArchiveLimits};
+use crate::model::{hash_key_of, object_id_of, Ingestion, IngestionKey, Report};
+use crate::parsers::{EntryContext, EntryParser, ObjectSource, ParsedEntry, ParserRegistry, ReportSink, SyncFormats};
+use crate::quarantine::{load_stage_of, quarantine, QuarantineManifest};
+use crate::retraction::retract_object;
 use crate::runtime_error::RuntimeError;
+use crate::sensors::{assigned_units_of, SensorsSeen};
+use crate::tar::TarReader;
+use crate::timezone::VesselTimezones;
+use async_compression::futures::bufread::GzipDecoder;
 use async_zip::base::read::stream::ZipFileReader;
-use async_zip::ZipEntry;
-use aws_sdk_dynamodb::types::{PutRequest, WriteRequest};
+use async_zip::error::ZipError;
+use aws_sdk_dynamodb::types::AttributeValue;
 use aws_sdk_dynamodb::Client as DynamoDbClient;
+use aws_sdk_s3::primitives::ByteStream;
 use aws_sdk_s3::Client as S3Client;
-use chrono::{DateTime, Datelike};
+use chrono::Utc;
+use futures::future::join_all;
+use futures::io::BufReader;
+use futures::AsyncRead;
 use lazy_regex::regex_captures;
 use log::{error, info, trace, warn};
-use serde::Deserialize;
+use rand::{thread_rng, Rng};
+use serde::Serialize;
 use serde_dynamo::to_item;
-use serde_json::{from_str, Value};
+use serde_json::to_string;
+use std::cmp::min;
 use std::collections::HashMap;
+use std::env::var;
+use std::future::Future;
+use std::io::Read;
+use std::num::ParseIntError;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::mpsc::{channel, Receiver, Sender};
+use tokio::task::{spawn_blocking, JoinSet};
+use tokio::time::sleep;
+use tokio::try_join;
+use tokio_util::compat::TokioAsyncReadCompatExt;
 use uuid::Uuid;
+use wrzasqpl_commons_aws::DynamoDbDao;
 
 static CHUNK_SIZE: usize = 25;
+static DEFAULT_WRITE_ATTEMPTS: u32 = 8;
+static DEFAULT_WRITE_CONCURRENCY: usize = 4;
+static DEFAULT_QUARANTINE_PREFIX: &str = "quarantine/";
+static BACKOFF_BASE_MS: u64 = 50;
+static BACKOFF_CAP_MS: u64 = 5000;
+static DEFAULT_MAX_TOTAL_SIZE: u64 = 1024 * 1024 * 1024;
+static DEFAULT_MAX_ENTRY_SIZE: u64 = 512 * 1024 * 1024;
+static DEFAULT_MAX_ENTRIES: usize = 1000;
+static DEFAULT_MAX_COMPRESSION_RATIO: u64 = 100;
+static ENTRY_CHUNKS_BUFFER: usize = 4;
+static RECORDS_BUFFER: usize = CHUNK_SIZE * 4;
 
-// model structures for IVMSv1
+#[doc = "Loader tuning options."]
+pub struct LoaderConfig {
+    #[doc = "Reports table name."]
+    pub table_name: String,
+    #[doc = "Sensor catalog table name."]
+    pub sensors_table_name: String,
+    #[doc = "Number of conditional `PutItem` attempts for each throttled record before giving up."]
+    pub write_attempts: u32,
+    #[doc = "Number of record chunks written at once."]
+    pub write_concurrency: usize,
+    #[doc = "Key prefix under which malformed archives are copied."]
+    pub quarantine_prefix: String,
+    #[doc = "Safety caps for processed archives."]
+    pub limits: ArchiveLimits,
+    #[doc = "Loads objects again, even if they are already recorded in the ledger."]
+    pub reload: bool,
+    #[doc = "Series rows ingested for each customer."]
+    pub event_filters: EventFilters,
+    #[doc = "Timezones in which report dates of each vessel are determined."]
+    pub vessel_timezones: VesselTimezones,
+}
+
+impl LoaderConfig {
+    pub fn from_env() -> Result<Self, RuntimeError> {
+        Ok(Self {
+            table_name: var("REPORTS_TABLE")?,
+            sensors_table_name: var("SENSORS_TABLE")?,
+            write_attempts: var_or("WRITE_ATTEMPTS", DEFAULT_WRITE_ATTEMPTS)?,
+            write_concurrency: var_or("WRITE_CONCURRENCY", DEFAULT_WRITE_CONCURRENCY)?,
+            quarantine_prefix: var("QUARANTINE_PREFIX").unwrap_or(DEFAULT_QUARANTINE_PREFIX.into()),
+            limits: archive_limits_from_env()?,
+            reload: false,
+            event_filters: EventFilters::from_env()?,
+            vessel_timezones: VesselTimezones::from_env()?,
+        })
+    }
+}
 
-#[derive(Deserialize)]
-struct ReportValueEntry {
-    sensor_text: String,
-    value: Vec<Value>,
+#[doc = "Archive safety caps, configured same way as for the loader."]
+pub fn archive_limits_from_env() -> Result<ArchiveLimits, RuntimeError> {
+    Ok(ArchiveLimits {
+        max_total_size: var_or("MAX_ARCHIVE_SIZE", DEFAULT_MAX_TOTAL_SIZE)?,
+        max_entry_size: var_or("MAX_ENTRY_SIZE", DEFAULT_MAX_ENTRY_SIZE)?,
+        max_entries: var_or("MAX_ARCHIVE_ENTRIES", DEFAULT_MAX_ENTRIES)?,
+        max_compression_ratio: var_or("MAX_COMPRESSION_RATIO", DEFAULT_MAX_COMPRESSION_RATIO)?,
+    })
 }
 
-#[derive(Deserialize)]
-struct ReportsSeries {
-    columns: Vec<String>,
-    values: Vec<Vec<Value>>,
+fn var_or<T: FromStr<Err = ParseIntError>>(name: &str, default: T) -> Result<T, RuntimeError> {
+    Ok(var(name)
+        .ok()
+        .map(|value| value.parse())
+        .transpose()?
+        .unwrap_or(default))
 }
 
-#[derive(Deserialize)]
-struct ReportsResults {
-    series: Vec<ReportsSeries>,
+// "full jitter" variant of capped exponential backoff
+pub(crate) fn backoff_delay(attempt: u32) -> Duration {
+    let ceiling = min(
+        BACKOFF_CAP_MS,
+        BACKOFF_BASE_MS.saturating_mul(2u64.saturating_pow(attempt)),
+    );
+
+    Duration::from_millis(thread_rng().gen_range(0..=ceiling))
 }
 
-#[derive(Deserialize)]
-struct ReportsData {
-    results: Vec<ReportsResults>,
+// blocking reader of entry content that is read asynchronously from the archive
+struct ChannelReader {
+    chunks: Receiver<Vec<u8>>,
+    chunk: Vec<u8>,
+    position: usize,
 }
 
-// end of IVMSv1
+impl ChannelReader {
+    fn new(chunks: Receiver<Vec<u8>>) -> Self {
+        Self {
+            chunks,
+            chunk: vec![],
+            position: 0,
+        }
+    }
+}
+
+impl Read for ChannelReader {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        while self.position >= self.chunk.len() {
+            match self.chunks.blocking_recv() {
+                Some(chunk) => {
+                    self.chunk = chunk;
+                    self.position = 0;
+                }
+                // closed channel means end of entry
+                None => return Ok(0),
+            }
+        }
+
+        let size = min(buf.len(), self.chunk.len() - self.position);
+        buf[..size].copy_from_slice(&self.chunk[self.position..self.position + size]);
+        self.position += size;
+
+        Ok(size)
+    }
+}
+
+// passes parsed records back to the asynchronous writer
+struct ChannelSink {
+    records: Sender<Report>,
+    skipped: usize,
+    filtered: usize,
+}
+
+impl ReportSink for ChannelSink {
+    fn report(&mut self, report: Report) -> Result<(), RuntimeError> {
+        self.records
+            .blocking_send(report)
+            .map_err(|_| RuntimeError::EntryStreamClosed)
+    }
+
+    fn skip(&mut self, reason: String) {
+        warn!("{}", reason);
+        self.skipped += 1;
+    }
+
+    fn filter_out(&mut self) {
+        self.filtered += 1;
+    }
+}
 
 struct DynamoDbBuffer<'a> {
     client: &'a DynamoDbClient,
-    table_name: String,
+    config: &'a LoaderConfig,
+    parsers: &'a ParserRegistry,
     customer_id: Uuid,
     vessel_id: Uuid,
-    buffer: Vec<WriteRequest>,
+    object: ObjectSource,
+    buffer: Vec<HashMap<String, AttributeValue>>,
+    writes: WritePipeline,
+    saved: usize,
+    skipped: usize,
+    filtered: usize,
+    stale: usize,
+    sensors: SensorsSeen,
+    units: Arc<HashMap<String, String>>,
 }
 
 impl<'a> DynamoDbBuffer<'a> {
     fn new(
         client: &'a DynamoDbClient,
-        table_name: String,
+        config: &'a LoaderConfig,
+        parsers: &'a ParserRegistry,
         customer_id: &'a str,
         vessel_id: &'a str,
+        object: ObjectSource,
     ) -> Result<Self, RuntimeError> {
         Ok(Self {
             client,
-            table_name,
+            config,
+            parsers,
             customer_id: Uuid::parse_str(customer_id)?,
             vessel_id: Uuid::parse_str(vessel_id)?,
+            object,
             buffer: vec![],
+            writes: WritePipeline::new(config.write_concurrency),
+            saved: 0,
+            skipped: 0,
+            filtered: 0,
+            stale: 0,
+            sensors: SensorsSeen::default(),
+            units: Arc::default(),
         })
     }
 
     async fn save_record(&mut self, entity: Report) -> Result<(), RuntimeError> {
-        let item = Some(to_item(&entity)?);
+        self.sensors.record(&entity);
+        self.buffer.push(to_item(&entity)?);
+        self.saved += 1;
 
-        self.write(PutRequest::builder().set_item(item).build()?).await?;
+        if self.buffer.len() >= CHUNK_SIZE {
+            self.save().await
+        } else {
+            Ok(())
+        }
+    }
+
+    async fn flush(&mut self) -> Result<(), RuntimeError> {
+        if !self.buffer.is_empty() {
+            self.save().await?;
+        }
+
+        self.writes.finish().await?;
+        // records are counted as saved when they are submitted, only after all writes are done it's known which were stale
+        self.stale = self.writes.stale;
+        self.saved -= self.stale;
 
         Ok(())
     }
 
-    async fn save_report(&mut self, report_name: String, data: HashMap<String, &Value>) -> Result<(), RuntimeError> {
-        for (key, payload) in data
+    async fn save(&mut self) -> Result<(), RuntimeError> {
+        // this passes owned records and also clears buffer
+        let batch = self.buffer.drain(..).collect();
+
+        self.writes
+            .submit(write_batch(
+                self.client.clone(),
+                self.config.table_name.clone(),
+                self.config.write_attempts,
+                batch,
+            ))
+            .await
+    }
+}
+
+type Item = HashMap<String, AttributeValue>;
+
+fn recorded_at_of(item: &Item) -> Option<&String> {
+    item.get("source")
+        .and_then(|source| source.as_m().ok())
+        .and_then(|source| source.get("recordedAt"))
+        .and_then(|recorded_at| recorded_at.as_s().ok())
+}
+
+// records of the chunk are written concurrently, returns number of stale records
+async fn write_batch(
+    client: DynamoDbClient,
+    table_name: String,
+    write_attempts: u32,
+    batch: Vec<Item>,
+) -> Result<usize, RuntimeError> {
+    let mut stale = 0;
+    let mut unprocessed = 0;
+
+    for result in join_all(
+        batch
             .into_iter()
-            .filter(|item| item.0.parse::<f64>().is_ok())
-            .filter_map(|item| {
-                item.1
-                    .as_str()
-                    .and_then(|value| from_str::<ReportValueEntry>(value).ok())
-                    .map(|value| (item.0, value))
-            })
-        {
-            if let Value::String(value) = &payload.value[0] {
-                self.save_record(Report {
-                    customer_id: self.customer_id,
-                    vessel_id: self.vessel_id,
-                    report_name: report_name.clone(),
-                    field_name: key,
-                    value: value.clone(),
-                    label: payload.sensor_text.clone(),
-                })
-                .await?;
-            }
+            .map(|item| write_record(&client, &table_name, write_attempts, item)),
+    )
+    .await
+    {
+        match result? {
+            WriteResult::Written => {}
+            WriteResult::Stale => stale += 1,
+            WriteResult::Throttled => unprocessed += 1,
         }
+    }
 
-        Ok(())
+    match unprocessed {
+        0 => Ok(stale),
+        _ => Err(RuntimeError::UnprocessedItems(unprocessed)),
     }
+}
+
+enum WriteResult {
+    Written,
+    // already stored value comes from newer sensor reading
+    Stale,
+    Throttled,
+}
+
+// last writer wins based on sensor reading time, not on upload order, so late upload of old archive doesn't overwrite
+// newer values (timestamps are all serialized with the same precision, so they can be compared as strings) - every
+// record is written conditionally, also one of the field that is not stored yet, as it may be written by concurrent
+// chunk or another invocation in the meantime
+async fn write_record(
+    client: &DynamoDbClient,
+    table_name: &str,
+    write_attempts: u32,
+    item: Item,
+) -> Result<WriteResult, RuntimeError> {
+    let recorded_at = recorded_at_of(&item).cloned().map(AttributeValue::S);
+    let mut attempt = 1;
 
-    async fn save_data_series(&mut self, series: &ReportsSeries) -> Result<(), RuntimeError> {
-        for row in &series.values {
-            let record: HashMap<String, &Value> = series
-                .columns
-                .iter()
-                .zip(row)
-                .map(|(key, value)| (key.clone(), value))
-                .collect();
+    loop {
+        let request = client
+            .put_item()
+            .table_name(table_name)
+            .set_item(Some(item.clone()))
+            .set_condition_expression(
+                recorded_at
+                    .as_ref()
+                    .map(|_| "attribute_not_exists(#source.#recordedAt) OR #source.#recordedAt <= :recordedAt".into()),
+            )
+            .set_expression_attribute_names(recorded_at.as_ref().map(|_| {
+                HashMap::from([
+                    ("#source".into(), "source".into()),
+                    ("#recordedAt".into(), "recordedAt".into()),
+                ])
+            }))
+            .set_expression_attribute_values(
+                recorded_at
+                    .clone()
+                    .map(|recorded_at| HashMap::from([(":recordedAt".into(), recorded_at)])),
+            );
 
-            if let (Some(Value::Number(time)), Some(Value::String(event_text))) =
-                (record.get("time"), record.get("event_text"))
+        let error = match request.send().await {
+            Ok(_) => return Ok(WriteResult::Written),
+            Err(error) => error,
+        };
+
+        match error.as_service_error() {
+            Some(service_error) if service_error.is_conditional_check_failed_exception() => {
+                trace!("Stale record: {:?}", item);
+                return Ok(WriteResult::Stale);
+            }
+            Some(service_error)
+                if service_error.is_provisioned_throughput_exceeded_exception()
+                    || service_error.is_request_limit_exceeded() =>
             {
-                match time.as_i64().and_then(|secs| DateTime::from_timestamp(secs, 0)) {
-                    None => {
-                        warn!("Could not handle record with invalid date: {}", time);
-                    }
-                    Some(date) => {
-                        self.save_report(
-                            format!("{}-{}-{}.{}", date.year(), date.month(), date.day(), event_text),
-                            record,
-                        )
-                        .await?;
-                    }
+                if attempt >= write_attempts {
+                    error!("Rejected record: {:?}", item);
+                    return Ok(WriteResult::Throttled);
                 }
+
+                let delay = backoff_delay(attempt);
+                warn!("Record write was throttled, retrying in {}ms.", delay.as_millis());
+                sleep(delay).await;
+                attempt += 1;
             }
+            _ => return Err(error.into()),
+        }
+    }
+}
+
+// keeps limited number of batch writes running in the background
+struct WritePipeline {
+    in_flight: JoinSet<Result<usize, RuntimeError>>,
+    concurrency: usize,
+    // number of records skipped by finished writes, as they were older than stored ones
+    stale: usize,
+}
+
+impl WritePipeline {
+    fn new(concurrency: usize) -> Self {
+        Self {
+            in_flight: JoinSet::new(),
+            concurrency: concurrency.max(1),
+            stale: 0,
+        }
+    }
+
+    async fn submit(
+        &mut self,
+        write: impl Future<Output = Result<usize, RuntimeError>> + Send + 'static,
+    ) -> Result<(), RuntimeError> {
+        // failures of already finished writes are reported as soon as possible
+        while let Some(result) = self.in_flight.try_join_next() {
+            self.stale += result??;
+        }
+
+        // back-pressure - next batch is not sent until there is a free slot
+        while self.in_flight.len() >= self.concurrency {
+            self.join_next().await?;
         }
 
+        self.in_flight.spawn(write);
+
         Ok(())
     }
 
-    async fn save_sensors_data(&mut self, data: String) -> Result<(), RuntimeError> {
-        let sensors = from_str::<ReportsData>(data.as_str())?;
+    async fn finish(&mut self) -> Result<(), RuntimeError> {
+        while !self.in_flight.is_empty() {
+            self.join_next().await?;
+        }
 
-        for series in sensors.results.iter().flat_map(|result| &result.series) {
-            self.save_data_series(series).await?;
+        Ok(())
+    }
+
+    async fn join_next(&mut self) -> Result<(), RuntimeError> {
+        if let Some(result) = self.in_flight.join_next().await {
+            self.stale += result??;
         }
 
         Ok(())
     }
+}
+
+// receiver of archive entries content
+trait EntryProcessor {
+    fn find_parser(&self, path: &str) -> Option<Arc<dyn EntryParser>>;
+
+    async fn process_entry(
+        &mut self,
+        parser: Arc<dyn EntryParser>,
+        path: &str,
+        entry: &mut (impl AsyncRead + Unpin),
+        guard: &mut ArchiveGuard<'_>,
+    ) -> Result<(), RuntimeError>;
+}
+
+impl EntryProcessor for DynamoDbBuffer<'_> {
+    fn find_parser(&self, path: &str) -> Option<Arc<dyn EntryParser>> {
+        self.parsers.find(path)
+    }
+
+    // entry content is streamed through the parser, so memory usage doesn't depend on entry size
+    async fn process_entry(
+        &mut self,
+        parser: Arc<dyn EntryParser>,
+        path: &str,
+        entry: &mut (impl AsyncRead + Unpin),
+        guard: &mut ArchiveGuard<'_>,
+    ) -> Result<(), RuntimeError> {
+        let (chunks, chunks_receiver) = channel(ENTRY_CHUNKS_BUFFER);
+        let (records_sender, mut records) = channel(RECORDS_BUFFER);
+        let context = EntryContext {
+            customer_id: self.customer_id,
+            vessel_id: self.vessel_id,
+            object: self.object.clone(),
+            entry_path: path.into(),
+            events: self.config.event_filters.for_customer(&self.customer_id),
+            units: self.units.clone(),
+            timezone: self.config.vessel_timezones.for_vessel(&self.vessel_id),
+        };
+
+        // JSON parsing is blocking, so it runs on a separate thread, bounded channels provide back-pressure
+        let parsing = spawn_blocking(move || {
+            let mut sink = ChannelSink {
+                records: records_sender,
+                skipped: 0,
+                filtered: 0,
+            };
 
-    async fn process_entry(&mut self, entry: ZipEntry, data: String) -> Result<(), RuntimeError> {
-        if let Ok(filename) = entry.filename().as_str() {
-            info!("Processing ZIP entry {}.", filename);
+            parser
+                .parse(&context, &mut ChannelReader::new(chunks_receiver), &mut sink)
+                .map(|()| (sink.skipped, sink.filtered))
+        });
 
-            match filename {
-                "data/reports.json" => self.save_sensors_data(data).await?,
-                _ => trace!("Unknown data entry {}.", filename),
+        let feed = async move {
+            loop {
+                let chunk = guard.read_chunk(entry).await?;
+
+                // parser stops reading only on failure, which is reported by its own result
+                if chunk.is_empty() || chunks.send(chunk).await.is_err() {
+                    return Ok::<(), RuntimeError>(());
+                }
+            }
+        };
+        let store = async {
+            while let Some(report) = records.recv().await {
+                self.save_record(report).await?;
             }
-        }
+
+            Ok::<(), RuntimeError>(())
+        };
+
+        try_join!(feed, store)?;
+        let (skipped, filtered) = parsing.await??;
+        self.skipped += skipped;
+        self.filtered += filtered;
 
         Ok(())
     }
+}
 
-    async fn write(&mut self, record: PutRequest) -> Result<(), RuntimeError> {
-        self.buffer.push(WriteRequest::builder().put_request(record).build());
+// parses entries in memory instead of storing them
+struct MemoryProcessor<'a> {
+    parsers: &'a ParserRegistry,
+    context: EntryContext,
+    parsed: ParsedEntry,
+}
 
-        if self.buffer.len() >= CHUNK_SIZE {
-            self.save().await
-        } else {
-            Ok(())
+impl EntryProcessor for MemoryProcessor<'_> {
+    fn find_parser(&self, path: &str) -> Option<Arc<dyn EntryParser>> {
+        self.parsers.find(path)
+    }
+
+    async fn process_entry(
+        &mut self,
+        parser: Arc<dyn EntryParser>,
+        path: &str,
+        entry: &mut (impl AsyncRead + Unpin),
+        guard: &mut ArchiveGuard<'_>,
+    ) -> Result<(), RuntimeError> {
+        let mut data = vec![];
+        loop {
+            let chunk = guard.read_chunk(entry).await?;
+            if chunk.is_empty() {
+                break;
+            }
+            data.extend(chunk);
         }
+
+        self.context.entry_path = path.into();
+
+        parser.parse(&self.context, &mut data.as_slice(), &mut self.parsed)
     }
+}
 
-    async fn flush(&mut self) -> Result<(), RuntimeError> {
-        if self.buffer.is_empty() {
-            Ok(())
-        } else {
-            self.save().await
+struct ArchiveReader<'b, 'a, P: EntryProcessor> {
+    processor: &'b mut P,
+    guard: ArchiveGuard<'a>,
+    // keeps track of currently processed entry, so that it can be reported in case of failure
+    current_entry: Option<String>,
+}
+
+impl<'b, 'a, P: EntryProcessor> ArchiveReader<'b, 'a, P> {
+    fn new(processor: &'b mut P, limits: &'a ArchiveLimits) -> Self {
+        Self {
+            processor,
+            guard: ArchiveGuard::new(limits),
+            current_entry: None,
         }
     }
 
-    async fn save(&mut self) -> Result<(), RuntimeError> {
-        let response = self
-            .client
-            .batch_write_item()
-            // this passes owned records and also clears buffer
-            .request_items(self.table_name.clone(), self.buffer.drain(..).collect())
-            .send()
-            .await?;
-        if let Some(items) = response.unprocessed_items() {
-            for record in items.values().flatten() {
-                error!(
-                    "Rejected record: {:?}",
-                    record.put_request.as_ref().map(PutRequest::item)
-                );
+    async fn read(&mut self, object_key: &str, body: ByteStream, size: u64) -> Result<(), RuntimeError> {
+        let (head, mut body) = peek(body.into_async_read().compat()).await?;
+
+        match detect_format(object_key, &head) {
+            Some(InputFormat::Zip) => self.read_zip(body).await,
+            Some(InputFormat::TarGzip) => self.read_tar(GzipDecoder::new(BufReader::new(body)), size).await,
+            Some(InputFormat::Gzip) => {
+                let entry = EntryInfo {
+                    name: entry_name_of(object_key),
+                    regular: true,
+                    compressed_size: size,
+                    declared_size: 0,
+                };
+
+                self.read_entry(entry, &mut GzipDecoder::new(BufReader::new(body)))
+                    .await
+            }
+            Some(InputFormat::Json) => {
+                let entry = EntryInfo {
+                    name: entry_name_of(object_key),
+                    regular: true,
+                    compressed_size: size,
+                    declared_size: size,
+                };
+
+                self.read_entry(entry, &mut body).await
             }
+            None => Err(RuntimeError::UnrecognizedInput),
         }
+    }
+
+    async fn read_zip(&mut self, body: impl AsyncRead + Unpin) -> Result<(), RuntimeError> {
+        let mut zip = ZipFileReader::new(body);
+
+        while let Some(mut entry) = zip.next_with_entry().await.map_err(|error| match error {
+            ZipError::FeatureNotSupported("encryption") => RuntimeError::EncryptedArchive,
+            error => error.into(),
+        })? {
+            let reader = entry.reader_mut();
+            let meta = reader.entry().to_owned();
+            let info = EntryInfo {
+                name: String::from_utf8_lossy(meta.filename().as_bytes()).into(),
+                regular: !meta.dir()?,
+                compressed_size: meta.compressed_size(),
+                declared_size: meta.uncompressed_size(),
+            };
+
+            self.read_entry(info, reader).await?;
+            if reader.compute_hash() != meta.crc32() {
+                return Err(ZipError::CRC32CheckError.into());
+            }
+
+            zip = entry.skip().await?;
+            self.current_entry = None;
+        }
+
+        Ok(())
+    }
+
+    // tar.gz is compressed as a whole, so there is no per-entry compressed size
+    async fn read_tar(&mut self, body: impl AsyncRead + Unpin, size: u64) -> Result<(), RuntimeError> {
+        let mut tar = TarReader::new(body);
+
+        while let Some(entry) = tar.next_entry().await? {
+            let info = EntryInfo {
+                name: entry.name,
+                regular: entry.regular,
+                compressed_size: size,
+                declared_size: entry.size,
+            };
+
+            self.read_entry(info, &mut tar.entry_reader()).await?;
+            self.current_entry = None;
+        }
+
+        Ok(())
+    }
+
+    async fn read_entry(
+        &mut self,
+        entry: EntryInfo,
+        reader: &mut (impl AsyncRead + Unpin),
+    ) -> Result<(), RuntimeError> {
+        self.current_entry = Some(entry.name.clone());
+        self.guard.open_entry(&entry)?;
+
+        if entry.regular {
+            match self.processor.find_parser(&entry.name) {
+                Some(parser) => {
+                    info!("Processing entry {} with {} parser.", entry.name, parser.name());
+
+                    self.processor
+                        .process_entry(parser, &entry.name, reader, &mut self.guard)
+                        .await?;
+                }
+                None => trace!("Unknown data entry {}.", entry.name),
+            }
+        }
+
+        // skipped content is decompressed as well, so it also counts towards limits
+        while !self.guard.read_chunk(reader).await?.is_empty() {}
 
         Ok(())
     }
 }
 
-pub async fn load_reports(
+#[doc = "Reads archive same way as `load_reports` does, but collects records in memory instead of storing them. Records \
+parsed before failure are returned together with the entry that was being read at that time."]
+pub async fn parse_archive(
+    parsers: &ParserRegistry,
+    limits: &ArchiveLimits,
+    context: EntryContext,
+    body: ByteStream,
+    size: u64,
+) -> (Result<(), RuntimeError>, Option<String>, ParsedEntry) {
+    let object_key = context.object.object_key.clone();
+    let mut processor = MemoryProcessor {
+        parsers,
+        context,
+        parsed: ParsedEntry::default(),
+    };
+    let mut archive = ArchiveReader::new(&mut processor, limits);
+    let result = archive.read(object_key.as_str(), body, size).await;
+    let current_entry = archive.current_entry;
+
+    (result, current_entry, processor.parsed)
+}
+
+#[derive(Serialize)]
+#[serde(tag = "outcome", rename_all = "camelCase", rename_all_fields = "camelCase")]
+#[doc = "Result of processing single S3 object."]
+pub enum LoadOutcome {
+    Ingested {
+        saved: usize,
+        skipped: usize,
+        filtered: usize,
+        stale: usize,
+        quarantine_key: Option<String>,
+        quarantine_reason: Option<String>,
+    },
+    AlreadyProcessed,
+    Quarantined {
+        quarantine_key: String,
+        quarantine_reason: String,
+    },
+    UnsupportedFormat {
+        prefix: String,
+    },
+    Ignored,
+    #[doc = "Object was removed, so were report fields loaded from it."]
+    Retracted {
+        removed: usize,
+        ingestions: usize,
+    },
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct LoadSummary<'a> {
+    bucket_name: &'a str,
+    object_key: &'a str,
+    #[serde(flatten)]
+    outcome: &'a LoadOutcome,
+}
+
+#[doc = "Splits sync object key into format prefix, customer ID and vessel ID."]
+pub fn sync_key_of(object_key: &str) -> Option<(&str, &str, &str)> {
+    regex_captures!(
+        "^([^/]+)/SYNC/([0-9a-f-]{36})/([0-9a-f-]{36})/.*\\.(?:zip|json|gz|tgz)$",
+        object_key
+    )
+    .map(|(_, prefix, customer_id, vessel_id)| (prefix, customer_id, vessel_id))
+}
+
+async fn load_object(
     s3: &S3Client,
     dynamodb: &DynamoDbClient,
-    table_name: String,
-    bucket_name: String,
-    object_key: String,
-) -> Result<(), RuntimeError> {
-    let stream = s3
-        .get_object()
-        .bucket(bucket_name)
-        .key(object_key.clone())
-        .send()
-        .await?
-        .body
-        .into_async_read();
-
-    info!("Processing S3 key {}.", object_key.clone());
-
-    if let Some((_, customer_id, vessel_id)) =
-        regex_captures!("^v1/SYNC/([0-9a-f-]{36})/([0-9a-f-]{36})/.*\\.zip$", &object_key)
+    ledger: &DynamoDbDao,
+    formats: &SyncFormats,
+    config: &LoaderConfig,
+    bucket_name: &str,
+    object_key: &str,
+) -> Result<LoadOutcome, RuntimeError> {
+    // quarantined copies are not meant to be loaded again
+    if object_key.starts_with(config.quarantine_prefix.as_str()) {
+        return Ok(LoadOutcome::Ignored);
+    }
+
+    let Some((prefix, customer_id, vessel_id)) = sync_key_of(object_key) else {
+        return Ok(LoadOutcome::UnsupportedFormat {
+            prefix: object_key.split('/').next().unwrap_or_default().into(),
+        });
+    };
+    let Some(parsers) = formats.find(prefix) else {
+        return Ok(LoadOutcome::UnsupportedFormat { prefix: prefix.into() });
+    };
+
+    let object = s3.get_object().bucket(bucket_name).key(object_key).send().await?;
+
+    let e_tag = object.e_tag().unwrap_or_default().trim_matches('"').to_string();
+    let version_id = object.version_id().map(String::from);
+    let manifest = |stage, entry, error: &RuntimeError| QuarantineManifest {
+        bucket_name: bucket_name.into(),
+        object_key: object_key.into(),
+        e_tag: e_tag.clone(),
+        stage,
+        entry,
+        reason: error.to_string(),
+        quarantined_at: Utc::now(),
+    };
+
+    let object_source = ObjectSource {
+        bucket_name: bucket_name.into(),
+        object_key: object_key.into(),
+        version_id: version_id.clone(),
+        ingested_at: Utc::now(),
+    };
+    let mut buffer = match DynamoDbBuffer::new(dynamodb, config, parsers, customer_id, vessel_id, object_source) {
+        Ok(buffer) => buffer,
+        Err(error) => {
+            return match load_stage_of(&error) {
+                Some(stage) => {
+                    let manifest = manifest(stage, None, &error);
+
+                    Ok(LoadOutcome::Quarantined {
+                        quarantine_key: quarantine(s3, &config.quarantine_prefix, &manifest).await?,
+                        quarantine_reason: manifest.reason,
+                    })
+                }
+                None => Err(error),
+            }
+        }
+    };
+
+    let hash_key = hash_key_of(&buffer.customer_id, &buffer.vessel_id);
+    if !config.reload
+        && ledger
+            .load::<Ingestion>(IngestionKey {
+                customer_and_vessel_id: hash_key.clone(),
+                object_id: object_id_of(bucket_name, object_key, &e_tag),
+            })
+            .await?
+            .is_some()
     {
-        let mut buffer = DynamoDbBuffer::new(dynamodb, table_name, customer_id, vessel_id)?;
-        let mut zip = ZipFileReader::with_tokio(stream);
+        return Ok(LoadOutcome::AlreadyProcessed);
+    }
 
-        while let Some(mut entry) = zip.next_with_entry().await? {
-            let reader = entry.reader_mut();
-            let meta = reader.entry().to_owned();
+    let sensors = DynamoDbDao::new(dynamodb.clone(), config.sensors_table_name.clone());
+    buffer.units = Arc::new(assigned_units_of(&sensors, hash_key.as_str()).await?);
 
-            if !meta.dir()? {
-                let mut data = String::with_capacity(meta.uncompressed_size() as usize);
-                reader.read_to_string_checked(&mut data).await?;
-                buffer.process_entry(meta, data).await?;
+    let size = u64::try_from(object.content_length().unwrap_or_default()).unwrap_or_default();
+    let mut archive = ArchiveReader::new(&mut buffer, &config.limits);
+    let result = archive.read(object_key, object.body, size).await;
+    let current_entry = archive.current_entry;
+
+    // records read before malformed part are still valid, so they are kept
+    let (quarantine_key, quarantine_reason) = match result {
+        Ok(()) => (None, None),
+        Err(error) => match load_stage_of(&error) {
+            Some(stage) => {
+                let manifest = manifest(stage, current_entry, &error);
+
+                (
+                    Some(quarantine(s3, &config.quarantine_prefix, &manifest).await?),
+                    Some(manifest.reason),
+                )
             }
+            None => return Err(error),
+        },
+    };
 
-            zip = entry.skip().await?;
+    buffer.flush().await?;
+    buffer
+        .sensors
+        .save(
+            dynamodb,
+            config.sensors_table_name.as_str(),
+            &buffer.customer_id,
+            &buffer.vessel_id,
+        )
+        .await?;
+
+    ledger
+        .save(&mut Ingestion {
+            customer_id: buffer.customer_id,
+            vessel_id: buffer.vessel_id,
+            bucket_name: bucket_name.into(),
+            object_key: object_key.into(),
+            e_tag,
+            version_id,
+            saved: buffer.saved,
+            skipped: buffer.skipped,
+            filtered: buffer.filtered,
+            stale: buffer.stale,
+            quarantine_key: quarantine_key.clone(),
+            quarantine_reason: quarantine_reason.clone(),
+            finished_at: Utc::now(),
+        })
+        .await?;
+
+    Ok(LoadOutcome::Ingested {
+        saved: buffer.saved,
+        skipped: buffer.skipped,
+        filtered: buffer.filtered,
+        stale: buffer.stale,
+        quarantine_key,
+        quarantine_reason,
+    })
+}
+
+pub async fn load_reports(
+    s3: &S3Client,
+    dynamodb: &DynamoDbClient,
+    ledger: &DynamoDbDao,
+    formats: &SyncFormats,
+    config: &LoaderConfig,
+    object: ObjectRef,
+) -> Result<LoadOutcome, RuntimeError> {
+    let bucket_name = object.bucket_name;
+    let object_key = object.object_key;
+
+    let outcome = match object.event {
+        ObjectEvent::Created => {
+            info!("Processing S3 key {}.", object_key);
+
+            load_object(s3, dynamodb, ledger, formats, config, &bucket_name, &object_key).await?
+        }
+        ObjectEvent::Removed { version_id } => {
+            info!("Retracting S3 key {}.", object_key);
+
+            retract_object(
+                dynamodb,
+                ledger,
+                config,
+                &bucket_name,
+                &object_key,
+                version_id.as_deref(),
+            )
+            .await?
+        }
+    };
+    let summary = to_string(&LoadSummary {
+        bucket_name: &bucket_name,
+        object_key: &object_key,
+        outcome: &outcome,
+    })?;
+
+    match outcome {
+        LoadOutcome::UnsupportedFormat { .. } => warn!("{}", summary),
+        _ => info!("{}", summary),
+    }
+
+    Ok(outcome)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::limits::ArchiveLimits;
+    use crate::loader::{
+        backoff_delay, parse_archive, ChannelReader, LoadOutcome, LoadSummary, WritePipeline, BACKOFF_CAP_MS,
+    };
+    use crate::model::ReportValue;
+    use crate::parsers::{fixture_context, ParsedEntry, ParserRegistry};
+    use crate::runtime_error::RuntimeError;
+    use aws_sdk_s3::primitives::ByteStream;
+    use serde_json::{json, to_value};
+    use std::io::Read;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::time::Duration;
+    use tokio::sync::mpsc::channel;
+    use tokio::task::spawn_blocking;
+    use tokio::time::sleep;
+    use uuid::{uuid, Uuid};
+
+    const CUSTOMER_ID: Uuid = uuid!("00000000-0000-0000-0000-000000000000");
+    const VESSEL_ID: Uuid = uuid!("00000000-0000-0000-0000-000000000001");
+
+    static LIMITS: ArchiveLimits = ArchiveLimits {
+        max_total_size: 1024 * 1024,
+        max_entry_size: 1024 * 1024,
+        max_entries: 10,
+        max_compression_ratio: 100,
+    };
+
+    async fn read_fixture(
+        object_key: &str,
+        data: &'static [u8],
+        limits: &ArchiveLimits,
+    ) -> (Result<(), RuntimeError>, Option<String>, ParsedEntry) {
+        let parsers = ParserRegistry::ivms_v1().unwrap();
+        let mut context = fixture_context(CUSTOMER_ID, VESSEL_ID);
+        context.object.object_key = object_key.into();
+
+        parse_archive(
+            &parsers,
+            limits,
+            context,
+            ByteStream::from_static(data),
+            data.len() as u64,
+        )
+        .await
+    }
+
+    async fn assert_fixture(object_key: &str, data: &'static [u8]) {
+        let (result, _, parsed) = read_fixture(object_key, data, &LIMITS).await;
+        result.unwrap();
+
+        assert_eq!(3, parsed.reports.len());
+        assert!(parsed.skipped.is_empty());
+        assert_eq!("2024-01-05.daily", parsed.reports[0].report_name);
+        assert!(parsed
+            .reports
+            .iter()
+            .any(|report| report.label == "Fuel (l)" && report.value == ReportValue::Number(13.5)));
+    }
+
+    #[tokio::test]
+    async fn read_input_formats() {
+        assert_fixture("v1/SYNC/sync.zip", include_bytes!("../tests/fixtures/sync.zip")).await;
+        assert_fixture("v1/SYNC/sync.tar.gz", include_bytes!("../tests/fixtures/sync.tar.gz")).await;
+        assert_fixture(
+            "v1/SYNC/reports.json.gz",
+            include_bytes!("../tests/fixtures/reports.json.gz"),
+        )
+        .await;
+        assert_fixture("v1/SYNC/reports.json", include_bytes!("../tests/fixtures/reports.json")).await;
+    }
+
+    #[tokio::test]
+    async fn read_unrecognized_input() {
+        let (result, _, _) = read_fixture("v1/SYNC/sync.zip", b"not an archive", &LIMITS).await;
+
+        assert!(matches!(result, Err(RuntimeError::UnrecognizedInput)));
+    }
+
+    #[tokio::test]
+    async fn read_exceeding_limits() {
+        let limits = ArchiveLimits {
+            max_entry_size: 100,
+            ..LIMITS
+        };
+        let (result, current_entry, _) = read_fixture(
+            "v1/SYNC/sync.tar.gz",
+            include_bytes!("../tests/fixtures/sync.tar.gz"),
+            &limits,
+        )
+        .await;
+
+        assert!(matches!(result, Err(RuntimeError::EntrySizeLimitExceeded(100))));
+        assert_eq!(Some("data/reports.json".into()), current_entry);
+    }
+
+    #[test]
+    fn backoff_delay_capped() {
+        assert!(backoff_delay(1).as_millis() <= 100);
+        assert!(backoff_delay(3).as_millis() <= 400);
+        assert!(backoff_delay(64).as_millis() <= BACKOFF_CAP_MS as u128);
+    }
+
+    // tracks number of concurrently running writes
+    #[derive(Clone, Default)]
+    struct WriteCounter {
+        running: Arc<AtomicUsize>,
+        peak: Arc<AtomicUsize>,
+        finished: Arc<AtomicUsize>,
+    }
+
+    impl WriteCounter {
+        async fn write(self, result: Result<usize, RuntimeError>) -> Result<usize, RuntimeError> {
+            let running = self.running.fetch_add(1, Ordering::SeqCst) + 1;
+            self.peak.fetch_max(running, Ordering::SeqCst);
+            sleep(Duration::from_millis(10)).await;
+            self.running.fetch_sub(1, Ordering::SeqCst);
+            self.finished.fetch_add(1, Ordering::SeqCst);
+
+            result
+        }
+    }
+
+    #[tokio::test]
+    async fn write_pipeline_concurrency() {
+        let counter = WriteCounter::default();
+        let mut pipeline = WritePipeline::new(3);
+
+        for _ in 0..10 {
+            pipeline.submit(counter.clone().write(Ok(2))).await.unwrap();
+            assert!(pipeline.in_flight.len() <= 3);
+        }
+        pipeline.finish().await.unwrap();
+
+        assert_eq!(3, counter.peak.load(Ordering::SeqCst));
+        assert_eq!(10, counter.finished.load(Ordering::SeqCst));
+        assert!(pipeline.in_flight.is_empty());
+        assert_eq!(20, pipeline.stale);
+    }
+
+    #[tokio::test]
+    async fn write_pipeline_failure() {
+        let counter = WriteCounter::default();
+        let mut pipeline = WritePipeline::new(2);
+
+        pipeline.submit(counter.clone().write(Ok(0))).await.unwrap();
+        pipeline
+            .submit(counter.clone().write(Err(RuntimeError::UnprocessedItems(3))))
+            .await
+            .unwrap();
+
+        assert!(matches!(
+            pipeline.finish().await,
+            Err(RuntimeError::UnprocessedItems(3))
+        ));
+    }
+
+    #[tokio::test]
+    async fn channel_reader() {
+        let (chunks, receiver) = channel(1);
+        let reading = spawn_blocking(move || {
+            let mut data = String::new();
+            ChannelReader::new(receiver).read_to_string(&mut data).map(|_| data)
+        });
+
+        for chunk in ["{\"results\"", "", ": []}"] {
+            chunks.send(chunk.as_bytes().to_vec()).await.unwrap();
         }
+        drop(chunks);
 
-        buffer.flush().await?;
+        assert_eq!("{\"results\": []}", reading.await.unwrap().unwrap());
     }
 
-    Ok(())
+    #[test]
+    fn load_summary() {
+        assert_eq!(
+            json!({
+                "bucketName": "upload",
+                "objectKey": "v3/SYNC/test.zip",
+                "outcome": "unsupportedFormat",
+                "prefix": "v3",
+            }),
+            to_value(LoadSummary {
+                bucket_name: "upload",
+                object_key: "v3/SYNC/test.zip",
+                outcome: &LoadOutcome::UnsupportedFormat { prefix: "v3".into() },
+            })
+            .unwrap()
+        );
+        assert_eq!(
+            json!({
+                "bucketName": "upload",
+                "objectKey": "v1/SYNC/test.zip",
+                "outcome": "ingested",
+                "saved": 2,
+                "skipped": 1,
+                "filtered": 4,
+                "stale": 3,
+                "quarantineKey": null,
+                "quarantineReason": null,
+            }),
+            to_value(LoadSummary {
+                bucket_name: "upload",
+                object_key: "v1/SYNC/test.zip",
+                outcome: &LoadOutcome::Ingested {
+                    saved: 2,
+                    skipped: 1,
+                    filtered: 4,
+                    stale: 3,
+                    quarantine_key: None,
+                    quarantine_reason: None,
+                },
+            })
+            .unwrap()
+        );
+    }
 }
@@ -5,53 +5,103 @@
  */
 
 #![feature(fn_traits)]
-#![feature(future_join)]
 #![feature(unboxed_closures)]
 
-mod api;
-mod loader;
-mod model;
-mod report_dao;
-mod runtime_error;
-
-use crate::api::{FetchRequest, ReportResponse};
-use crate::loader::load_reports as loader;
-use crate::model::{hash_key_of, VesselReportPageToken};
-use crate::runtime_error::RuntimeError;
 use aws_config::load_defaults;
-use aws_lambda_events::s3::S3Event;
-use aws_lambda_events::sns::SnsEvent;
+use aws_lambda_events::sqs::{SqsBatchResponse, SqsEvent};
 use aws_sdk_dynamodb::Client as DynamoDbClient;
 use aws_sdk_s3::Client as S3Client;
 use aws_smithy_runtime_api::client::behavior_version::BehaviorVersion;
+use ivms_reports_aggregator::api::{FetchRequest, IngestionsRequest, IngestionsResponse, ReportResponse};
+use ivms_reports_aggregator::events::{objects_of, process_queue_batch};
+use ivms_reports_aggregator::loader::{load_reports as loader, LoaderConfig};
+use ivms_reports_aggregator::migration::{migrate_report_names, MigrationRequest, MigrationResponse};
+use ivms_reports_aggregator::model::{hash_key_of, IngestionKey, VesselReportPageToken};
+use ivms_reports_aggregator::parsers::SyncFormats;
+use ivms_reports_aggregator::report_dao::ReportDao;
+use ivms_reports_aggregator::runtime_error::RuntimeError;
+use ivms_reports_aggregator::sensors::canonical_names_of;
 use lambda_runtime::{Error, LambdaEvent};
-use serde_json::from_str;
+use serde_json::Value;
 use std::env::var;
 use std::future::Future;
 use std::rc::Rc;
 use tokio::main as tokio_main;
-use urlencoding::decode;
 use wrzasqpl_commons_aws::{run_lambda, DynamoDbDao, LambdaError};
 
 fn fetch_reports(
-    dao: Rc<DynamoDbDao>,
+    dao: Rc<ReportDao>,
+    dynamo_db: Rc<DynamoDbClient>,
+    sensors_table: Rc<String>,
 ) -> impl Fn<(LambdaEvent<FetchRequest>,), Output = impl Future<Output = Result<ReportResponse, RuntimeError>>> {
     move |event: LambdaEvent<FetchRequest>| {
         let dao = dao.clone();
+        let dynamo_db = dynamo_db.clone();
+        let sensors_table = sensors_table.clone();
         let hash_key = hash_key_of(&event.payload.customer_id, &event.payload.vessel_id);
 
         async move {
-            dao.query_index(
-                "vesselReports".into(),
-                "customerAndVesselId".into(),
+            let page_token = event
+                .payload
+                .page_token
+                .map(|report_key| {
+                    // field names never contain colon, so the last one splits report name from field name
+                    report_key
+                        .rsplit_once(':')
+                        .map(|(report_name, _)| report_name.to_string())
+                        .map(|report_name| VesselReportPageToken {
+                            customer_and_vessel_id: hash_key.clone(),
+                            report_name,
+                            report_key,
+                        })
+                        .ok_or(RuntimeError::InvalidFetchRequest)
+                })
+                .transpose()?;
+
+            let include_source = event.payload.include_source;
+            let include_units = event.payload.include_units;
+
+            let page = match (event.payload.report_name, event.payload.from, event.payload.to) {
+                (Some(report_name), None, None) => dao.query_report(hash_key.clone(), report_name, page_token).await,
+                (None, Some(from), Some(to)) if from <= to => {
+                    dao.query_reports(hash_key.clone(), from, to, page_token).await
+                }
+                _ => return Err(RuntimeError::InvalidFetchRequest),
+            }?;
+
+            Ok(
+                ReportResponse::resolve(page, include_source, include_units, |field_names| {
+                    canonical_names_of(
+                        dynamo_db.as_ref(),
+                        sensors_table.as_str(),
+                        hash_key.as_str(),
+                        field_names,
+                    )
+                })
+                .await,
+            )
+        }
+    }
+}
+
+fn fetch_ingestions(
+    dao: Rc<DynamoDbDao>,
+) -> impl Fn<(LambdaEvent<IngestionsRequest>,), Output = impl Future<Output = Result<IngestionsResponse, RuntimeError>>>
+{
+    move |event: LambdaEvent<IngestionsRequest>| {
+        let dao = dao.clone();
+        let hash_key = hash_key_of(&event.payload.customer_id, &event.payload.vessel_id);
+
+        async move {
+            dao.query(
                 hash_key.clone(),
-                event.payload.page_token.map(|field_name| VesselReportPageToken {
+                event.payload.page_token.map(|object_id| IngestionKey {
                     customer_and_vessel_id: hash_key.clone(),
-                    field_name,
+                    object_id,
                 }),
             )
             .await
-            .map(ReportResponse::from)
+            .map(IngestionsResponse::from)
             .map_err(RuntimeError::from)
         }
     }
@@ -60,28 +110,29 @@ fn fetch_reports(
 fn load_reports(
     s3: Rc<S3Client>,
     dynamo_db: Rc<DynamoDbClient>,
-    table: Rc<String>,
-) -> impl Fn<(LambdaEvent<SnsEvent>,), Output = impl Future<Output = Result<(), RuntimeError>>> {
-    move |event: LambdaEvent<SnsEvent>| {
+    ledger: Rc<DynamoDbDao>,
+    formats: Rc<SyncFormats>,
+    config: Rc<LoaderConfig>,
+) -> impl Fn<(LambdaEvent<Value>,), Output = impl Future<Output = Result<(), RuntimeError>>> {
+    move |event: LambdaEvent<Value>| {
         let s3 = s3.clone();
         let dynamo_db = dynamo_db.clone();
-        let table = table.clone();
+        let ledger = ledger.clone();
+        let formats = formats.clone();
+        let config = config.clone();
 
         async move {
-            for sns_record in event.payload.records {
-                // flat_map is not an option because of `?` within closure
-                for s3_record in from_str::<S3Event>(sns_record.sns.message.as_str())?.records {
-                    loader(
-                        s3.as_ref(),
-                        dynamo_db.as_ref(),
-                        table.as_str().to_string(),
-                        s3_record.s3.bucket.name.ok_or(RuntimeError::MalformedS3Event)?,
-                        decode(s3_record.s3.object.key.ok_or(RuntimeError::MalformedS3Event)?.as_str())
-                            .map_err(|_| RuntimeError::MalformedS3Event)?
-                            .into_owned(),
-                    )
-                    .await?;
-                }
+            // all delivery paths (S3, SNS, SQS, EventBridge) are unwrapped to the same object references
+            for object in objects_of(event.payload)? {
+                loader(
+                    s3.as_ref(),
+                    dynamo_db.as_ref(),
+                    ledger.as_ref(),
+                    formats.as_ref(),
+                    config.as_ref(),
+                    object,
+                )
+                .await?;
             }
 
             Ok(())
@@ -89,18 +140,147 @@ fn load_reports(
     }
 }
 
+fn load_queued_reports(
+    s3: Rc<S3Client>,
+    dynamo_db: Rc<DynamoDbClient>,
+    ledger: Rc<DynamoDbDao>,
+    formats: Rc<SyncFormats>,
+    config: Rc<LoaderConfig>,
+) -> impl Fn<(LambdaEvent<SqsEvent>,), Output = impl Future<Output = Result<SqsBatchResponse, RuntimeError>>> {
+    move |event: LambdaEvent<SqsEvent>| {
+        let s3 = s3.clone();
+        let dynamo_db = dynamo_db.clone();
+        let ledger = ledger.clone();
+        let formats = formats.clone();
+        let config = config.clone();
+
+        async move {
+            Ok(process_queue_batch(event.payload, |object| {
+                loader(
+                    s3.as_ref(),
+                    dynamo_db.as_ref(),
+                    ledger.as_ref(),
+                    formats.as_ref(),
+                    config.as_ref(),
+                    object,
+                )
+            })
+            .await)
+        }
+    }
+}
+
+fn migrate_reports(
+    dynamo_db: Rc<DynamoDbClient>,
+    table: Rc<String>,
+) -> impl Fn<(LambdaEvent<MigrationRequest>,), Output = impl Future<Output = Result<MigrationResponse, RuntimeError>>> {
+    move |event: LambdaEvent<MigrationRequest>| {
+        let dynamo_db = dynamo_db.clone();
+        let table = table.clone();
+
+        async move { migrate_report_names(dynamo_db.as_ref(), table.as_str().to_string(), event.payload.page_token).await }
+    }
+}
+
 #[tokio_main]
 async fn main() -> Result<(), Error> {
     let config = &load_defaults(BehaviorVersion::v2023_11_09()).await;
     let client = DynamoDbClient::new(config);
-    let table = var("REPORTS_TABLE")?;
 
     run_lambda!(
-        "reports:fetch": fetch_reports(Rc::new(DynamoDbDao::new(client, table))),
+        "reports:fetch": fetch_reports(
+            Rc::new(ReportDao::new(client.clone(), var("REPORTS_TABLE")?)),
+            Rc::new(client),
+            Rc::new(var("SENSORS_TABLE")?),
+        ),
         "reports:load": load_reports(
             Rc::new(S3Client::new(config)),
-            Rc::new(client),
-            Rc::new(table),
+            Rc::new(client.clone()),
+            Rc::new(DynamoDbDao::new(client, var("INGESTIONS_TABLE")?)),
+            Rc::new(SyncFormats::with_defaults()?),
+            Rc::new(LoaderConfig::from_env()?),
         ),
+        "reports:load-queue": load_queued_reports(
+            Rc::new(S3Client::new(config)),
+            Rc::new(client.clone()),
+            Rc::new(DynamoDbDao::new(client, var("INGESTIONS_TABLE")?)),
+            Rc::new(SyncFormats::with_defaults()?),
+            Rc::new(LoaderConfig::from_env()?),
+        ),
+        "ingestions:fetch": fetch_ingestions(Rc::new(DynamoDbDao::new(client, var("INGESTIONS_TABLE")?))),
+        "reports:migrate": migrate_reports(Rc::new(client), Rc::new(var("REPORTS_TABLE")?)),
     )
 }
+
+#[cfg(test)]
+mod tests {
+    use std::collections::{HashMap, HashSet};
+    use std::fs::{read_dir, read_to_string};
+
+    // variables without default value, read when the handler is set up
+    static HANDLER_VARIABLES: &[(&str, &[&str])] = &[
+        ("reports:fetch", &["REPORTS_TABLE", "SENSORS_TABLE"]),
+        ("reports:load", &["REPORTS_TABLE", "SENSORS_TABLE", "INGESTIONS_TABLE"]),
+        (
+            "reports:load-queue",
+            &["REPORTS_TABLE", "SENSORS_TABLE", "INGESTIONS_TABLE"],
+        ),
+        ("ingestions:fetch", &["INGESTIONS_TABLE"]),
+        ("reports:migrate", &["REPORTS_TABLE"]),
+    ];
+
+    fn indentation_of(line: &str) -> usize {
+        line.len() - line.trim_start().len()
+    }
+
+    // handlers of Lambda functions defined in the template, with names of their environment variables
+    fn deployed_handlers(template: &str) -> HashMap<String, HashSet<String>> {
+        let mut handlers = HashMap::new();
+        let mut handler = None;
+        let mut variables_indentation = None;
+
+        for line in template.lines().filter(|line| !line.trim().is_empty()) {
+            let indentation = indentation_of(line);
+            let line = line.trim();
+
+            if variables_indentation.is_some_and(|variables| indentation <= variables) {
+                variables_indentation = None;
+            }
+
+            if let Some(name) = line.strip_prefix("Handler: ") {
+                let name = name.trim_matches('"').to_string();
+                handlers.entry(name.clone()).or_insert_with(HashSet::new);
+                handler = Some(name);
+            } else if line == "Variables:" {
+                variables_indentation = Some(indentation);
+            } else if let (Some(_), Some(handler)) = (variables_indentation, &handler) {
+                if let Some((variable, _)) = line.split_once(':') {
+                    handlers.get_mut(handler).unwrap().insert(variable.to_string());
+                }
+            }
+        }
+
+        handlers
+    }
+
+    #[test]
+    fn deployed_handlers_environment() {
+        let mut deployed = HashMap::new();
+        for entry in read_dir(concat!(env!("CARGO_MANIFEST_DIR"), "/infrastructure/cloudformation")).unwrap() {
+            deployed.extend(deployed_handlers(
+                read_to_string(entry.unwrap().path()).unwrap().as_str(),
+            ));
+        }
+        let required = HashMap::<&str, &[&str]>::from_iter(HANDLER_VARIABLES.iter().copied());
+
+        assert!(!deployed.is_empty());
+        for (handler, variables) in deployed {
+            let required = required
+                .get(handler.as_str())
+                .unwrap_or_else(|| panic!("Unknown handler {handler}"));
+            for variable in required.iter() {
+                assert!(variables.contains(*variable), "{handler} lacks {variable}");
+            }
+        }
+    }
+}
@@ -0,0 +1,171 @@
+/*
+ * This file is part of the IVMS Online.
+ *
+ * @copyright 2024 © by Rafał Wrzeszcz - Wrzasq.pl.
+ */
+
+use crate::model::{normalize_report_name, sort_key_of, ReportKey};
+use crate::runtime_error::RuntimeError;
+use aws_sdk_dynamodb::types::AttributeValue::S;
+use aws_sdk_dynamodb::types::{AttributeValue, DeleteRequest, WriteRequest};
+use aws_sdk_dynamodb::Client as DynamoDbClient;
+use futures::{stream, StreamExt};
+use log::{error, info};
+use serde::{Deserialize, Serialize};
+use serde_dynamo::{from_item, to_item};
+use std::collections::HashMap;
+
+static CHUNK_SIZE: usize = 25;
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MigrationRequest {
+    pub page_token: Option<ReportKey>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MigrationResponse {
+    pub migrated: usize,
+    pub failed: usize,
+    pub page_token: Option<ReportKey>,
+}
+
+struct ItemMigration {
+    item: HashMap<String, AttributeValue>,
+    old_key: HashMap<String, AttributeValue>,
+}
+
+fn migrate_item(mut item: HashMap<String, AttributeValue>) -> Option<ItemMigration> {
+    let report_name = normalize_report_name(item.get("reportName")?.as_s().ok()?)?;
+    let field_name = item.get("fieldName")?.as_s().ok()?.clone();
+    let old_key = HashMap::from([
+        (
+            "customerAndVesselId".to_string(),
+            item.get("customerAndVesselId")?.clone(),
+        ),
+        ("reportKey".to_string(), item.get("reportKey")?.clone()),
+    ]);
+
+    item.insert("reportKey".into(), S(sort_key_of(&report_name, &field_name)));
+    item.insert("reportName".into(), S(report_name));
+
+    Some(ItemMigration { item, old_key })
+}
+
+// never overwrites an item under zero-padded key, as it may hold newer value written by the loader
+async fn put_migrated(
+    client: &DynamoDbClient,
+    table_name: &str,
+    migration: &ItemMigration,
+) -> Result<bool, RuntimeError> {
+    match client
+        .put_item()
+        .table_name(table_name)
+        .set_item(Some(migration.item.clone()))
+        .condition_expression("attribute_not_exists(#reportKey)")
+        .expression_attribute_names("#reportKey", "reportKey")
+        .send()
+        .await
+    {
+        Ok(_) => Ok(true),
+        Err(error)
+            if error
+                .as_service_error()
+                .is_some_and(|error| error.is_conditional_check_failed_exception()) =>
+        {
+            Ok(false)
+        }
+        Err(error) => Err(error.into()),
+    }
+}
+
+async fn write(
+    client: &DynamoDbClient,
+    table_name: &str,
+    requests: Vec<WriteRequest>,
+) -> Result<Vec<WriteRequest>, RuntimeError> {
+    if requests.is_empty() {
+        return Ok(vec![]);
+    }
+
+    Ok(client
+        .batch_write_item()
+        .request_items(table_name, requests)
+        .send()
+        .await?
+        .unprocessed_items
+        .and_then(|mut items| items.remove(table_name))
+        .unwrap_or_default())
+}
+
+#[doc = "Rewrites single scan page of legacy report names into zero-padded format."]
+pub async fn migrate_report_names(
+    client: &DynamoDbClient,
+    table_name: String,
+    page_token: Option<ReportKey>,
+) -> Result<MigrationResponse, RuntimeError> {
+    let page = client
+        .scan()
+        .table_name(table_name.as_str())
+        .set_exclusive_start_key(page_token.map(to_item).transpose()?)
+        .send()
+        .await?;
+
+    let migrations = page
+        .items
+        .unwrap_or_default()
+        .into_iter()
+        .filter_map(migrate_item)
+        .collect::<Vec<ItemMigration>>();
+    let mut response = MigrationResponse {
+        migrated: 0,
+        failed: 0,
+        page_token: page.last_evaluated_key.map(from_item).transpose()?,
+    };
+
+    for chunk in migrations.chunks(CHUNK_SIZE) {
+        // old items can only be deleted once new ones are stored
+        let stored = stream::iter(chunk)
+            .map(|migration| put_migrated(client, table_name.as_str(), migration))
+            .buffer_unordered(CHUNK_SIZE)
+            .collect::<Vec<Result<bool, RuntimeError>>>()
+            .await
+            .into_iter()
+            .collect::<Result<Vec<bool>, RuntimeError>>()?;
+        let superseded = stored.iter().filter(|created| !**created).count();
+        if superseded > 0 {
+            // loader already stored the field under zero-padded key, legacy value is obsolete
+            info!("Kept {} records already stored under zero-padded name.", superseded);
+        }
+
+        let mut deletes = vec![];
+        for migration in chunk {
+            deletes.push(
+                WriteRequest::builder()
+                    .delete_request(
+                        DeleteRequest::builder()
+                            .set_key(Some(migration.old_key.clone()))
+                            .build()?,
+                    )
+                    .build(),
+            );
+        }
+
+        // left-overs of legacy items will be processed again by next run
+        let left = write(client, table_name.as_str(), deletes).await?.len();
+        if left > 0 {
+            error!("Failed to delete {} legacy records.", left);
+        }
+
+        response.migrated += chunk.len() - left;
+        response.failed += left;
+    }
+
+    info!(
+        "Migrated {} report records, {} failed.",
+        response.migrated, response.failed
+    );
+
+    Ok(response)
+}
@@ -6,6 +6,8 @@
 
 use aws_sdk_dynamodb::operation::put_item::builders::PutItemFluentBuilder;
 use aws_sdk_dynamodb::types::AttributeValue::S;
+use chrono::{DateTime, NaiveDate, Utc};
+use lazy_regex::regex_captures;
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 use wrzasqpl_commons_aws::DynamoDbEntity;
@@ -20,6 +22,69 @@ pub fn sort_key_of(report_name: &String, field_name: &String) -> String {
     format!("{report_name}:{field_name}")
 }
 
+#[inline(always)]
+pub fn object_id_of(bucket_name: &str, object_key: &str, e_tag: &str) -> String {
+    format!("{bucket_name}/{object_key}:{e_tag}")
+}
+
+#[inline(always)]
+pub fn report_name_of(date: &NaiveDate, event_text: &str) -> String {
+    format!("{}.{event_text}", date.format("%Y-%m-%d"))
+}
+
+#[doc = "Converts legacy, not zero-padded, report name into sortable one. Returns `None` for up-to-date names."]
+pub fn normalize_report_name(report_name: &str) -> Option<String> {
+    regex_captures!("^([0-9]{4})-([0-9]{1,2})-([0-9]{1,2})\\.(.*)$", report_name)
+        .and_then(|(_, year, month, day, event_text)| {
+            NaiveDate::from_ymd_opt(year.parse().ok()?, month.parse().ok()?, day.parse().ok()?)
+                .map(|date| report_name_of(&date, event_text))
+        })
+        .filter(|normalized| normalized != report_name)
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(untagged, from = "StoredReportValue")]
+#[doc = "Typed report field value."]
+pub enum ReportValue {
+    Null,
+    Number(f64),
+    Bool(bool),
+    Text(String),
+    List(Vec<ReportValue>),
+}
+
+impl From<String> for ReportValue {
+    fn from(value: String) -> Self {
+        match value.parse::<f64>() {
+            Ok(number) if number.is_finite() => Self::Number(number),
+            _ => Self::Text(value),
+        }
+    }
+}
+
+// legacy items were storing all values as strings, so numeric strings still need to be read as numbers
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum StoredReportValue {
+    Null,
+    Number(f64),
+    Bool(bool),
+    Text(String),
+    List(Vec<ReportValue>),
+}
+
+impl From<StoredReportValue> for ReportValue {
+    fn from(value: StoredReportValue) -> Self {
+        match value {
+            StoredReportValue::Null => Self::Null,
+            StoredReportValue::Number(number) => Self::Number(number),
+            StoredReportValue::Bool(flag) => Self::Bool(flag),
+            StoredReportValue::Text(text) => Self::from(text),
+            StoredReportValue::List(values) => Self::List(values),
+        }
+    }
+}
+
 #[derive(Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 #[doc = "Report entry entity."]
@@ -32,10 +97,48 @@ pub struct Report {
     pub report_name: String,
     #[doc = "Report field."]
     pub field_name: String,
-    #[doc = "Report field."]
-    pub value: String, // TODO: change to some numeric field?
+    #[doc = "Report value."]
+    pub value: ReportValue,
     #[doc = "Description test."]
     pub label: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[doc = "Origin of the value (not known for items loaded before it was tracked)."]
+    pub source: Option<ReportSource>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[doc = "Unit of the value, as assigned to the sensor or embedded in its label."]
+    pub unit: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[doc = "Value converted to SI unit (only for units with known conversion)."]
+    pub normalized: Option<NormalizedValue>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[doc = "Report field value in SI unit."]
+pub struct NormalizedValue {
+    pub value: ReportValue,
+    pub unit: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+#[doc = "Provenance of report field value."]
+pub struct ReportSource {
+    #[doc = "Source S3 bucket."]
+    pub bucket_name: String,
+    #[doc = "Source S3 object key."]
+    pub object_key: String,
+    #[doc = "Source object version (for versioned buckets)."]
+    pub version_id: Option<String>,
+    #[doc = "Path of the archive entry containing the value."]
+    pub entry_path: String,
+    #[doc = "Sensor reading time, from the series `time` column."]
+    pub recorded_at: DateTime<Utc>,
+    #[doc = "Time of loading the source object."]
+    pub ingested_at: DateTime<Utc>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[doc = "Timezone in which report date was determined (absent for items loaded before it was configurable - \
+those used UTC)."]
+    pub timezone: Option<String>,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -49,7 +152,8 @@ pub struct ReportKey {
 #[serde(rename_all = "camelCase")]
 pub struct VesselReportPageToken {
     pub customer_and_vessel_id: String,
-    pub field_name: String,
+    pub report_name: String,
+    pub report_key: String,
 }
 
 impl DynamoDbEntity<'_> for Report {
@@ -76,9 +180,132 @@ impl DynamoDbEntity<'_> for Report {
     }
 }
 
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+#[doc = "Processed sync archive ledger entry."]
+pub struct Ingestion {
+    #[doc = "Owner ID."]
+    pub customer_id: Uuid,
+    #[doc = "Vessel ID."]
+    pub vessel_id: Uuid,
+    #[doc = "Source S3 bucket."]
+    pub bucket_name: String,
+    #[doc = "Source S3 object key."]
+    pub object_key: String,
+    #[doc = "Source object ETag."]
+    pub e_tag: String,
+    #[doc = "Source object version (for versioned buckets)."]
+    pub version_id: Option<String>,
+    #[doc = "Number of saved report records."]
+    pub saved: usize,
+    #[doc = "Number of skipped report entries."]
+    pub skipped: usize,
+    #[serde(default)]
+    #[doc = "Number of series rows excluded by event filters."]
+    pub filtered: usize,
+    #[serde(default)]
+    #[doc = "Number of report records not written, because newer values were already stored."]
+    pub stale: usize,
+    #[doc = "Location of the quarantined copy, if archive turned out to be malformed."]
+    pub quarantine_key: Option<String>,
+    #[doc = "Reason of quarantining the archive."]
+    pub quarantine_reason: Option<String>,
+    #[doc = "Processing finish time."]
+    pub finished_at: DateTime<Utc>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct IngestionKey {
+    pub customer_and_vessel_id: String,
+    pub object_id: String,
+}
+
+impl DynamoDbEntity<'_> for Ingestion {
+    type Key = IngestionKey;
+
+    fn hash_key_name() -> String {
+        "customerAndVesselId".into()
+    }
+
+    fn build_key(&self) -> IngestionKey {
+        IngestionKey {
+            customer_and_vessel_id: hash_key_of(&self.customer_id, &self.vessel_id),
+            object_id: object_id_of(&self.bucket_name, &self.object_key, &self.e_tag),
+        }
+    }
+
+    fn handle_save(&mut self, request: PutItemFluentBuilder) -> PutItemFluentBuilder {
+        request
+            .item(
+                "customerAndVesselId",
+                S(hash_key_of(&self.customer_id, &self.vessel_id)),
+            )
+            .item(
+                "objectId",
+                S(object_id_of(&self.bucket_name, &self.object_key, &self.e_tag)),
+            )
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+#[doc = "Vessel sensor catalog entry."]
+pub struct Sensor {
+    #[doc = "Owner ID."]
+    pub customer_id: Uuid,
+    #[doc = "Vessel ID."]
+    pub vessel_id: Uuid,
+    #[doc = "Raw sensor key, as stored in report fields."]
+    pub field_name: String,
+    #[doc = "Sensor description from the most recent reading."]
+    pub label: String,
+    #[doc = "Time of the earliest loaded reading."]
+    pub first_seen_at: DateTime<Utc>,
+    #[doc = "Time of the latest loaded reading."]
+    pub last_seen_at: DateTime<Utc>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[doc = "Stable identifier under which the sensor is exposed by the API."]
+    pub canonical_name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[doc = "Unit of sensor values, takes precedence over the one embedded in the label."]
+    pub unit: Option<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SensorKey {
+    pub customer_and_vessel_id: String,
+    pub field_name: String,
+}
+
+impl DynamoDbEntity<'_> for Sensor {
+    type Key = SensorKey;
+
+    fn hash_key_name() -> String {
+        "customerAndVesselId".into()
+    }
+
+    fn build_key(&self) -> SensorKey {
+        SensorKey {
+            customer_and_vessel_id: hash_key_of(&self.customer_id, &self.vessel_id),
+            field_name: self.field_name.clone(),
+        }
+    }
+
+    fn handle_save(&mut self, request: PutItemFluentBuilder) -> PutItemFluentBuilder {
+        request.item(
+            "customerAndVesselId",
+            S(hash_key_of(&self.customer_id, &self.vessel_id)),
+        )
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use crate::model::Report;
+    use crate::model::{normalize_report_name, report_name_of, Ingestion, Report, ReportValue};
+    use chrono::{NaiveDate, Utc};
+    use serde_json::{from_value, json, to_value};
     use uuid::{uuid, Uuid};
     use wrzasqpl_commons_aws::DynamoDbEntity;
 
@@ -94,12 +321,106 @@ mod tests {
             vessel_id: VESSEL_ID,
             report_name: REPORT_NAME.into(),
             field_name: FIELD_NAME.into(),
-            value: "".to_string(),
+            value: ReportValue::Number(0.0),
             label: "".to_string(),
+            source: None,
+            unit: None,
+            normalized: None,
         };
         let key = report.build_key();
 
         assert_eq!(format!("{CUSTOMER_ID}:{VESSEL_ID}"), key.customer_and_vessel_id);
         assert_eq!(format!("{REPORT_NAME}:{FIELD_NAME}"), key.report_key);
     }
+
+    #[test]
+    fn build_ingestion_key() {
+        let ingestion = Ingestion {
+            customer_id: CUSTOMER_ID,
+            vessel_id: VESSEL_ID,
+            bucket_name: "upload".into(),
+            object_key: "v1/SYNC/test.zip".into(),
+            e_tag: "abc".into(),
+            version_id: None,
+            saved: 0,
+            skipped: 0,
+            filtered: 0,
+            stale: 0,
+            quarantine_key: None,
+            quarantine_reason: None,
+            finished_at: Utc::now(),
+        };
+        let key = ingestion.build_key();
+
+        assert_eq!(format!("{CUSTOMER_ID}:{VESSEL_ID}"), key.customer_and_vessel_id);
+        assert_eq!("upload/v1/SYNC/test.zip:abc", key.object_id);
+    }
+
+    #[test]
+    fn build_report_name() {
+        assert_eq!(
+            "2024-01-05.daily",
+            report_name_of(&NaiveDate::from_ymd_opt(2024, 1, 5).unwrap(), "daily")
+        );
+    }
+
+    #[test]
+    fn normalize_legacy_report_name() {
+        assert_eq!(Some("2024-01-05.daily".into()), normalize_report_name("2024-1-5.daily"));
+        assert_eq!(Some("2024-11-05.x.y".into()), normalize_report_name("2024-11-5.x.y"));
+    }
+
+    #[test]
+    fn normalize_current_report_name() {
+        assert!(normalize_report_name("2024-01-05.daily").is_none());
+    }
+
+    #[test]
+    fn normalize_unknown_report_name() {
+        assert!(normalize_report_name("2024.week2").is_none());
+        assert!(normalize_report_name("2024-2-30.daily").is_none());
+    }
+
+    #[test]
+    fn parse_report_value() {
+        assert_eq!(ReportValue::Number(12.5), ReportValue::from("12.5".to_string()));
+        assert_eq!(ReportValue::Text("on".into()), ReportValue::from("on".to_string()));
+        assert_eq!(ReportValue::Text("NaN".into()), ReportValue::from("NaN".to_string()));
+    }
+
+    #[test]
+    fn serialize_report_value() {
+        assert_eq!(json!(12.5), to_value(ReportValue::Number(12.5)).unwrap());
+        assert_eq!(json!(true), to_value(ReportValue::Bool(true)).unwrap());
+        assert_eq!(json!("on"), to_value(ReportValue::Text("on".into())).unwrap());
+        assert_eq!(json!(null), to_value(ReportValue::Null).unwrap());
+        assert_eq!(
+            json!([1.0, "on"]),
+            to_value(ReportValue::List(vec![
+                ReportValue::Number(1.0),
+                ReportValue::Text("on".into())
+            ]))
+            .unwrap()
+        );
+    }
+
+    #[test]
+    fn deserialize_report_value() {
+        assert_eq!(
+            ReportValue::Number(12.5),
+            from_value::<ReportValue>(json!(12.5)).unwrap()
+        );
+        assert_eq!(
+            ReportValue::Number(12.5),
+            from_value::<ReportValue>(json!("12.5")).unwrap()
+        );
+        assert_eq!(
+            ReportValue::Bool(false),
+            from_value::<ReportValue>(json!(false)).unwrap()
+        );
+        assert_eq!(
+            ReportValue::Text("on".into()),
+            from_value::<ReportValue>(json!("on")).unwrap()
+        );
+    }
 }
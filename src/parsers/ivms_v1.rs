@@ -0,0 +1,675 @@
+/*
+ * This file is part of the IVMS Online.
+ *
+ * @copyright 2024 © by Rafał Wrzeszcz - Wrzasq.pl.
+ */
+
+use crate::model::{report_name_of, Report, ReportValue};
+use crate::parsers::{EntryContext, EntryParser, ReportSink};
+use crate::runtime_error::RuntimeError;
+use crate::units::{normalize, unit_of};
+use chrono::{DateTime, Utc};
+use serde::de::{DeserializeSeed, Error, IgnoredAny, MapAccess, SeqAccess, Visitor};
+use serde::{Deserialize, Deserializer};
+use serde_json::{from_str, Value};
+use std::collections::HashMap;
+use std::fmt;
+use std::fmt::Formatter;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Seek, Write};
+use tempfile::tempfile;
+
+// model structures for IVMSv1
+
+#[derive(Deserialize)]
+pub(super) struct ReportValueEntry {
+    sensor_text: String,
+    value: Vec<Value>,
+}
+
+impl ReportValueEntry {
+    fn report_value(&self) -> Option<ReportValue> {
+        match self.value.as_slice() {
+            [] => None,
+            [value] => report_value_of(value),
+            values => values
+                .iter()
+                .map(report_value_of)
+                .collect::<Option<Vec<ReportValue>>>()
+                .map(ReportValue::List),
+        }
+    }
+}
+
+fn report_value_of(value: &Value) -> Option<ReportValue> {
+    match value {
+        Value::Null => Some(ReportValue::Null),
+        Value::Bool(flag) => Some(ReportValue::Bool(*flag)),
+        Value::Number(number) => number.as_f64().map(ReportValue::Number),
+        Value::String(text) => Some(ReportValue::from(text.clone())),
+        Value::Array(values) => values
+            .iter()
+            .map(report_value_of)
+            .collect::<Option<Vec<ReportValue>>>()
+            .map(ReportValue::List),
+        Value::Object(_) => None,
+    }
+}
+
+// end of IVMSv1
+
+// decodes single sensor cell of the series row
+pub(super) type CellDecoder = fn(&Value) -> Option<ReportValueEntry>;
+
+// in IVMSv1 each sensor cell is a JSON document embedded as string
+fn decode_embedded_cell(value: &Value) -> Option<ReportValueEntry> {
+    value
+        .as_str()
+        .and_then(|value| from_str::<ReportValueEntry>(value).ok())
+}
+
+// walks `results[].series[].values[]` passing rows one by one, so that whole document is never kept in memory
+struct SeriesWalker<'a> {
+    context: &'a EntryContext,
+    decode: CellDecoder,
+    sink: &'a mut dyn ReportSink,
+    // sink error is kept aside, as deserializer can only carry its own error type
+    failure: Option<RuntimeError>,
+}
+
+impl SeriesWalker<'_> {
+    fn row<E: Error>(&mut self, columns: &[String], row: &[Value]) -> Result<(), E> {
+        parse_row(self.context, columns, row, self.decode, self.sink).map_err(|error| {
+            let message = error.to_string();
+            self.failure = Some(error);
+            E::custom(message)
+        })
+    }
+}
+
+struct ReportsData<'w, 'a>(&'w mut SeriesWalker<'a>);
+
+impl<'de> DeserializeSeed<'de> for ReportsData<'_, '_> {
+    type Value = ();
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<(), D::Error> {
+        deserializer.deserialize_map(self)
+    }
+}
+
+impl<'de> Visitor<'de> for ReportsData<'_, '_> {
+    type Value = ();
+
+    fn expecting(&self, formatter: &mut Formatter) -> fmt::Result {
+        formatter.write_str("reports data object")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<(), A::Error> {
+        while let Some(key) = map.next_key::<String>()? {
+            match key.as_str() {
+                "results" => map.next_value_seed(ReportsResultsList(&mut *self.0))?,
+                _ => map.next_value::<IgnoredAny>().map(drop)?,
+            }
+        }
+
+        Ok(())
+    }
+}
+
+struct ReportsResultsList<'w, 'a>(&'w mut SeriesWalker<'a>);
+
+impl<'de> DeserializeSeed<'de> for ReportsResultsList<'_, '_> {
+    type Value = ();
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<(), D::Error> {
+        deserializer.deserialize_seq(self)
+    }
+}
+
+impl<'de> Visitor<'de> for ReportsResultsList<'_, '_> {
+    type Value = ();
+
+    fn expecting(&self, formatter: &mut Formatter) -> fmt::Result {
+        formatter.write_str("list of results")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<(), A::Error> {
+        while seq.next_element_seed(ReportsResults(&mut *self.0))?.is_some() {}
+
+        Ok(())
+    }
+}
+
+struct ReportsResults<'w, 'a>(&'w mut SeriesWalker<'a>);
+
+impl<'de> DeserializeSeed<'de> for ReportsResults<'_, '_> {
+    type Value = ();
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<(), D::Error> {
+        deserializer.deserialize_map(self)
+    }
+}
+
+impl<'de> Visitor<'de> for ReportsResults<'_, '_> {
+    type Value = ();
+
+    fn expecting(&self, formatter: &mut Formatter) -> fmt::Result {
+        formatter.write_str("results object")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<(), A::Error> {
+        while let Some(key) = map.next_key::<String>()? {
+            match key.as_str() {
+                "series" => map.next_value_seed(ReportsSeriesList(&mut *self.0))?,
+                _ => map.next_value::<IgnoredAny>().map(drop)?,
+            }
+        }
+
+        Ok(())
+    }
+}
+
+struct ReportsSeriesList<'w, 'a>(&'w mut SeriesWalker<'a>);
+
+impl<'de> DeserializeSeed<'de> for ReportsSeriesList<'_, '_> {
+    type Value = ();
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<(), D::Error> {
+        deserializer.deserialize_seq(self)
+    }
+}
+
+impl<'de> Visitor<'de> for ReportsSeriesList<'_, '_> {
+    type Value = ();
+
+    fn expecting(&self, formatter: &mut Formatter) -> fmt::Result {
+        formatter.write_str("list of series")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<(), A::Error> {
+        while seq.next_element_seed(ReportsSeries(&mut *self.0))?.is_some() {}
+
+        Ok(())
+    }
+}
+
+struct ReportsSeries<'w, 'a>(&'w mut SeriesWalker<'a>);
+
+impl<'de> DeserializeSeed<'de> for ReportsSeries<'_, '_> {
+    type Value = ();
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<(), D::Error> {
+        deserializer.deserialize_map(self)
+    }
+}
+
+impl<'de> Visitor<'de> for ReportsSeries<'_, '_> {
+    type Value = ();
+
+    fn expecting(&self, formatter: &mut Formatter) -> fmt::Result {
+        formatter.write_str("series object")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<(), A::Error> {
+        let mut columns = None;
+        // rows can only be streamed once columns are known, otherwise they are spooled to disk until then
+        let mut spool = None;
+
+        while let Some(key) = map.next_key::<String>()? {
+            match (key.as_str(), &columns) {
+                ("columns", _) => columns = Some(map.next_value::<Vec<String>>()?),
+                ("values", Some(columns)) => map.next_value_seed(ReportsRows {
+                    walker: &mut *self.0,
+                    columns,
+                })?,
+                ("values", None) => {
+                    let mut file = BufWriter::new(tempfile().map_err(A::Error::custom)?);
+                    map.next_value_seed(SpooledRows(&mut file))?;
+                    spool = Some(file);
+                }
+                _ => map.next_value::<IgnoredAny>().map(drop)?,
+            }
+        }
+
+        if let (Some(columns), Some(spool)) = (columns, spool) {
+            let mut file = spool.into_inner().map_err(A::Error::custom)?;
+            file.rewind().map_err(A::Error::custom)?;
+
+            for row in serde_json::Deserializer::from_reader(BufReader::new(file)).into_iter::<Vec<Value>>() {
+                self.0.row(&columns, &row.map_err(A::Error::custom)?)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+struct ReportsRows<'w, 'a, 'c> {
+    walker: &'w mut SeriesWalker<'a>,
+    columns: &'c [String],
+}
+
+impl<'de> DeserializeSeed<'de> for ReportsRows<'_, '_, '_> {
+    type Value = ();
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<(), D::Error> {
+        deserializer.deserialize_seq(self)
+    }
+}
+
+impl<'de> Visitor<'de> for ReportsRows<'_, '_, '_> {
+    type Value = ();
+
+    fn expecting(&self, formatter: &mut Formatter) -> fmt::Result {
+        formatter.write_str("list of rows")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<(), A::Error> {
+        while let Some(row) = seq.next_element::<Vec<Value>>()? {
+            self.walker.row(self.columns, &row)?;
+        }
+
+        Ok(())
+    }
+}
+
+// rows are written as consecutive JSON documents, to be read back once columns are known
+struct SpooledRows<'s>(&'s mut BufWriter<File>);
+
+impl<'de> DeserializeSeed<'de> for SpooledRows<'_> {
+    type Value = ();
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<(), D::Error> {
+        deserializer.deserialize_seq(self)
+    }
+}
+
+impl<'de> Visitor<'de> for SpooledRows<'_> {
+    type Value = ();
+
+    fn expecting(&self, formatter: &mut Formatter) -> fmt::Result {
+        formatter.write_str("list of rows")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<(), A::Error> {
+        while let Some(row) = seq.next_element::<Vec<Value>>()? {
+            serde_json::to_writer(&mut *self.0, &row).map_err(A::Error::custom)?;
+            self.0.write_all(b"\n").map_err(A::Error::custom)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[doc = "Parses series-based reports document, common for all IVMS versions."]
+pub(super) fn parse_reports_data(
+    context: &EntryContext,
+    data: &mut dyn Read,
+    decode: CellDecoder,
+    sink: &mut dyn ReportSink,
+) -> Result<(), RuntimeError> {
+    let mut walker = SeriesWalker {
+        context,
+        decode,
+        sink,
+        failure: None,
+    };
+    let mut deserializer = serde_json::Deserializer::from_reader(BufReader::new(data));
+
+    let result = ReportsData(&mut walker)
+        .deserialize(&mut deserializer)
+        .and_then(|()| deserializer.end());
+
+    match walker.failure {
+        Some(error) => Err(error),
+        None => Ok(result?),
+    }
+}
+
+fn parse_report(
+    context: &EntryContext,
+    report_name: String,
+    recorded_at: DateTime<Utc>,
+    data: HashMap<String, &Value>,
+    decode: CellDecoder,
+    sink: &mut dyn ReportSink,
+) -> Result<(), RuntimeError> {
+    // null column simply means there is no reading of given sensor in the row
+    for (key, payload) in data
+        .into_iter()
+        .filter(|item| item.0.parse::<f64>().is_ok() && !item.1.is_null())
+    {
+        match decode(payload).and_then(|entry| entry.report_value().map(|value| (entry.sensor_text, value))) {
+            Some((label, value)) => {
+                let unit = context.units.get(&key).cloned().or_else(|| unit_of(&label));
+
+                sink.report(Report {
+                    customer_id: context.customer_id,
+                    vessel_id: context.vessel_id,
+                    report_name: report_name.clone(),
+                    normalized: unit.as_deref().and_then(|unit| normalize(&value, unit)),
+                    field_name: key,
+                    value,
+                    label,
+                    source: Some(context.source_of(recorded_at)),
+                    unit,
+                })?
+            }
+            None => sink.skip(format!(
+                "Skipped empty or unsupported value of field {} in report {}: {}",
+                key, report_name, payload
+            )),
+        }
+    }
+
+    Ok(())
+}
+
+fn parse_row(
+    context: &EntryContext,
+    columns: &[String],
+    row: &[Value],
+    decode: CellDecoder,
+    sink: &mut dyn ReportSink,
+) -> Result<(), RuntimeError> {
+    let record: HashMap<String, &Value> = columns
+        .iter()
+        .zip(row)
+        .map(|(key, value)| (key.clone(), value))
+        .collect();
+
+    if let (Some(Value::Number(time)), Some(Value::String(event_text))) = (record.get("time"), record.get("event_text"))
+    {
+        if !context.events.accepts(event_text) {
+            sink.filter_out();
+            return Ok(());
+        }
+
+        match time.as_i64().and_then(|secs| DateTime::from_timestamp(secs, 0)) {
+            None => sink.warn(format!("Could not handle record with invalid date: {}", time)),
+            Some(date) => {
+                return parse_report(
+                    context,
+                    report_name_of(&context.timezone.date_of(&date), event_text),
+                    date,
+                    record,
+                    decode,
+                    sink,
+                );
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[doc = "IVMSv1 `data/reports.json` entry parser."]
+pub struct ReportsDataParser;
+
+impl EntryParser for ReportsDataParser {
+    fn name(&self) -> &str {
+        "IVMSv1 reports"
+    }
+
+    fn parse(
+        &self,
+        context: &EntryContext,
+        data: &mut dyn Read,
+        sink: &mut dyn ReportSink,
+    ) -> Result<(), RuntimeError> {
+        parse_reports_data(context, data, decode_embedded_cell, sink)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::event_filter::EventFilters;
+    use crate::model::{Report, ReportValue};
+    use crate::parsers::ivms_v1::{ReportValueEntry, ReportsDataParser};
+    use crate::parsers::{fixture_context, EntryParser, ParsedEntry, ReportSink};
+    use crate::runtime_error::RuntimeError;
+    use crate::timezone::ReportTimezone;
+    use chrono::DateTime;
+    use serde_json::{from_value, json};
+    use std::collections::HashMap;
+    use std::str::FromStr;
+    use std::sync::Arc;
+    use uuid::{uuid, Uuid};
+
+    const CUSTOMER_ID: Uuid = uuid!("00000000-0000-0000-0000-000000000000");
+    const VESSEL_ID: Uuid = uuid!("00000000-0000-0000-0000-000000000001");
+
+    // accepts only limited number of records
+    struct LimitedSink(usize);
+
+    impl ReportSink for LimitedSink {
+        fn report(&mut self, _report: Report) -> Result<(), RuntimeError> {
+            match self.0 {
+                0 => Err(RuntimeError::UnprocessedItems(1)),
+                _ => {
+                    self.0 -= 1;
+                    Ok(())
+                }
+            }
+        }
+
+        fn skip(&mut self, _reason: String) {}
+
+        fn filter_out(&mut self) {}
+    }
+
+    fn parse(data: &str, sink: &mut dyn ReportSink) -> Result<(), RuntimeError> {
+        ReportsDataParser.parse(&fixture_context(CUSTOMER_ID, VESSEL_ID), &mut data.as_bytes(), sink)
+    }
+
+    fn entry(value: serde_json::Value) -> ReportValueEntry {
+        from_value(json!({"sensor_text": "Test", "value": value})).unwrap()
+    }
+
+    #[test]
+    fn single_value() {
+        assert_eq!(Some(ReportValue::Number(12.5)), entry(json!(["12.5"])).report_value());
+        assert_eq!(Some(ReportValue::Number(3.0)), entry(json!([3])).report_value());
+        assert_eq!(Some(ReportValue::Bool(true)), entry(json!([true])).report_value());
+        assert_eq!(
+            Some(ReportValue::Text("on".into())),
+            entry(json!(["on"])).report_value()
+        );
+        assert_eq!(Some(ReportValue::Null), entry(json!([null])).report_value());
+    }
+
+    #[test]
+    fn multiple_values() {
+        assert_eq!(
+            Some(ReportValue::List(vec![
+                ReportValue::Number(1.0),
+                ReportValue::Bool(false)
+            ])),
+            entry(json!([1, false])).report_value()
+        );
+    }
+
+    #[test]
+    fn empty_value() {
+        assert!(entry(json!([])).report_value().is_none());
+    }
+
+    #[test]
+    fn unsupported_value() {
+        assert!(entry(json!([{"nested": 1}])).report_value().is_none());
+        assert!(entry(json!([1, {"nested": 1}])).report_value().is_none());
+    }
+
+    #[test]
+    fn parse_reports_data() {
+        let data = json!({
+            "results": [{
+                "series": [{
+                    "columns": ["time", "event_text", "1", "2", "3", "comment"],
+                    "values": [
+                        [1704412800, "daily", "{\"sensor_text\": \"Fuel\", \"value\": [\"12.5\"]}", null, "{\"sensor_text\": \"Empty\", \"value\": []}", "x"],
+                        ["invalid", "daily", "{\"sensor_text\": \"Fuel\", \"value\": [\"1\"]}", null, null, null],
+                        [i64::MAX, "daily", "{\"sensor_text\": \"Fuel\", \"value\": [\"1\"]}", null, null, null],
+                    ],
+                }],
+            }],
+        });
+        let mut parsed = ParsedEntry::default();
+        parse(data.to_string().as_str(), &mut parsed).unwrap();
+
+        assert_eq!(1, parsed.reports.len());
+        assert_eq!("2024-01-05.daily", parsed.reports[0].report_name);
+        assert_eq!("1", parsed.reports[0].field_name);
+        assert_eq!(ReportValue::Number(12.5), parsed.reports[0].value);
+        assert_eq!("Fuel", parsed.reports[0].label);
+        assert_eq!(1, parsed.skipped.len());
+        assert!(parsed.skipped[0].contains("field 3 in report 2024-01-05.daily"));
+        assert_eq!(1, parsed.warnings.len());
+    }
+
+    #[test]
+    fn report_source() {
+        let data = r#"{"results": [{"series": [{
+            "columns": ["time", "event_text", "1"],
+            "values": [[1704412815, "daily", "{\"sensor_text\": \"Fuel\", \"value\": [\"12.5\"]}"]]
+        }]}]}"#;
+        let mut parsed = ParsedEntry::default();
+        parse(data, &mut parsed).unwrap();
+
+        let source = parsed.reports[0].source.as_ref().unwrap();
+        assert_eq!("upload", source.bucket_name);
+        assert_eq!("v1/SYNC/sync.zip", source.object_key);
+        assert_eq!(Some("v1".into()), source.version_id);
+        assert_eq!("data/reports.json", source.entry_path);
+        assert_eq!(DateTime::from_timestamp(1704412815, 0).unwrap(), source.recorded_at);
+        assert_eq!(DateTime::from_timestamp(1704499200, 0).unwrap(), source.ingested_at);
+        assert_eq!(Some("UTC".into()), source.timezone);
+    }
+
+    #[test]
+    fn local_report_date() {
+        // 2024-01-05T20:00:00Z is already next day in Singapore
+        let data = r#"{"results": [{"series": [{
+            "columns": ["time", "event_text", "1"],
+            "values": [[1704484800, "daily", "{\"sensor_text\": \"Fuel\", \"value\": [\"12.5\"]}"]]
+        }]}]}"#;
+        let mut context = fixture_context(CUSTOMER_ID, VESSEL_ID);
+        context.timezone = ReportTimezone::from_str("Asia/Singapore").unwrap();
+        let mut parsed = ParsedEntry::default();
+        ReportsDataParser
+            .parse(&context, &mut data.as_bytes(), &mut parsed)
+            .unwrap();
+
+        assert_eq!("2024-01-06.daily", parsed.reports[0].report_name);
+        let source = parsed.reports[0].source.as_ref().unwrap();
+        assert_eq!(DateTime::from_timestamp(1704484800, 0).unwrap(), source.recorded_at);
+        assert_eq!(Some("Asia/Singapore".into()), source.timezone);
+    }
+
+    #[test]
+    fn filter_events() {
+        let data = r#"{"results": [{"series": [{
+            "columns": ["time", "event_text", "1"],
+            "values": [
+                [1704412800, "daily", "{\"sensor_text\": \"Fuel\", \"value\": [\"12.5\"]}"],
+                [1704412800, "debug-dump", "{\"sensor_text\": \"Fuel\", \"value\": [\"1\"]}"],
+                [1704412800, "debug-trace", "{\"sensor_text\": \"Fuel\", \"value\": [\"2\"]}"]
+            ]
+        }]}]}"#;
+        let mut context = fixture_context(CUSTOMER_ID, VESSEL_ID);
+        context.events = EventFilters::from_json(r#"{"*": {"deny": ["debug*"]}}"#)
+            .unwrap()
+            .for_customer(&CUSTOMER_ID);
+        let mut parsed = ParsedEntry::default();
+        ReportsDataParser
+            .parse(&context, &mut data.as_bytes(), &mut parsed)
+            .unwrap();
+
+        assert_eq!(1, parsed.reports.len());
+        assert_eq!("2024-01-05.daily", parsed.reports[0].report_name);
+        assert_eq!(2, parsed.filtered);
+    }
+
+    #[test]
+    fn assign_units() {
+        let data = r#"{"results": [{"series": [{
+            "columns": ["time", "event_text", "1", "2", "3"],
+            "values": [[
+                1704412800,
+                "daily",
+                "{\"sensor_text\": \"Fuel (l)\", \"value\": [\"12.5\"]}",
+                "{\"sensor_text\": \"Main engine\", \"value\": [\"1.5\"]}",
+                "{\"sensor_text\": \"Engine hours\", \"value\": [\"100\"]}"
+            ]]
+        }]}]}"#;
+        let mut context = fixture_context(CUSTOMER_ID, VESSEL_ID);
+        context.units = Arc::new(HashMap::from([("2".into(), "kW".into())]));
+        let mut parsed = ParsedEntry::default();
+        ReportsDataParser
+            .parse(&context, &mut data.as_bytes(), &mut parsed)
+            .unwrap();
+        parsed.reports.sort_by(|a, b| a.field_name.cmp(&b.field_name));
+
+        assert_eq!(Some("l".into()), parsed.reports[0].unit);
+        assert_eq!(ReportValue::Number(12.5), parsed.reports[0].value);
+        assert_eq!("m³", parsed.reports[0].normalized.as_ref().unwrap().unit);
+        // assigned in sensor catalog
+        assert_eq!(Some("kW".into()), parsed.reports[1].unit);
+        assert_eq!(
+            ReportValue::Number(1500.0),
+            parsed.reports[1].normalized.as_ref().unwrap().value
+        );
+        assert!(parsed.reports[2].unit.is_none());
+        assert!(parsed.reports[2].normalized.is_none());
+    }
+
+    #[test]
+    fn parse_columns_after_values() {
+        let data = r#"{
+            "results": [{
+                "statement_id": 0,
+                "series": [{
+                    "values": [
+                        [1704412800, "daily", "{\"sensor_text\": \"Fuel\", \"value\": [\"12.5\"]}"],
+                        [1704499200, "daily", "{\"sensor_text\": \"Fuel\", \"value\": [\"13\"]}"]
+                    ],
+                    "name": "reports",
+                    "columns": ["time", "event_text", "1"]
+                }]
+            }]
+        }"#;
+        let mut parsed = ParsedEntry::default();
+        parse(data, &mut parsed).unwrap();
+
+        assert_eq!(2, parsed.reports.len());
+        assert_eq!(ReportValue::Number(12.5), parsed.reports[0].value);
+        assert_eq!("2024-01-06.daily", parsed.reports[1].report_name);
+        assert_eq!(ReportValue::Number(13.0), parsed.reports[1].value);
+    }
+
+    #[test]
+    fn parse_invalid_data() {
+        assert!(parse("{", &mut ParsedEntry::default()).is_err());
+        assert!(parse(r#"{"results": []} {}"#, &mut ParsedEntry::default()).is_err());
+    }
+
+    #[test]
+    fn parse_sink_failure() {
+        let data = json!({
+            "results": [{
+                "series": [{
+                    "columns": ["time", "event_text", "1"],
+                    "values": [
+                        [1704412800, "daily", "{\"sensor_text\": \"Fuel\", \"value\": [\"12.5\"]}"],
+                        [1704499200, "daily", "{\"sensor_text\": \"Fuel\", \"value\": [\"13.5\"]}"],
+                    ],
+                }],
+            }],
+        });
+
+        assert!(matches!(
+            parse(data.to_string().as_str(), &mut LimitedSink(1)),
+            Err(RuntimeError::UnprocessedItems(1))
+        ));
+    }
+}
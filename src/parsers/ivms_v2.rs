@@ -0,0 +1,76 @@
+/*
+ * This file is part of the IVMS Online.
+ *
+ * @copyright 2024 © by Rafał Wrzeszcz - Wrzasq.pl.
+ */
+
+use crate::parsers::ivms_v1::{parse_reports_data, ReportValueEntry};
+use crate::parsers::{EntryContext, EntryParser, ReportSink};
+use crate::runtime_error::RuntimeError;
+use serde::Deserialize;
+use serde_json::Value;
+use std::io::Read;
+
+// IVMSv2 keeps the series layout, but sensor cells are plain JSON objects instead of embedded documents
+fn decode_native_cell(value: &Value) -> Option<ReportValueEntry> {
+    ReportValueEntry::deserialize(value).ok()
+}
+
+#[doc = "IVMSv2 `data/reports.json` entry parser."]
+pub struct ReportsDataV2Parser;
+
+impl EntryParser for ReportsDataV2Parser {
+    fn name(&self) -> &str {
+        "IVMSv2 reports"
+    }
+
+    fn parse(
+        &self,
+        context: &EntryContext,
+        data: &mut dyn Read,
+        sink: &mut dyn ReportSink,
+    ) -> Result<(), RuntimeError> {
+        parse_reports_data(context, data, decode_native_cell, sink)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::model::ReportValue;
+    use crate::parsers::ivms_v2::ReportsDataV2Parser;
+    use crate::parsers::{fixture_context, EntryParser, ParsedEntry};
+    use serde_json::json;
+    use uuid::{uuid, Uuid};
+
+    const CUSTOMER_ID: Uuid = uuid!("00000000-0000-0000-0000-000000000000");
+    const VESSEL_ID: Uuid = uuid!("00000000-0000-0000-0000-000000000001");
+
+    #[test]
+    fn parse_reports_data() {
+        let data = json!({
+            "results": [{
+                "series": [{
+                    "columns": ["time", "event_text", "1", "2"],
+                    "values": [
+                        [1704412800, "daily", {"sensor_text": "Fuel", "value": [12.5]}, "{\"sensor_text\": \"Old\", \"value\": [1]}"],
+                    ],
+                }],
+            }],
+        });
+        let mut parsed = ParsedEntry::default();
+        ReportsDataV2Parser
+            .parse(
+                &fixture_context(CUSTOMER_ID, VESSEL_ID),
+                &mut data.to_string().as_bytes(),
+                &mut parsed,
+            )
+            .unwrap();
+
+        assert_eq!(1, parsed.reports.len());
+        assert_eq!("2024-01-05.daily", parsed.reports[0].report_name);
+        assert_eq!(ReportValue::Number(12.5), parsed.reports[0].value);
+        assert_eq!("Fuel", parsed.reports[0].label);
+        // v1-style embedded cell is not valid in v2
+        assert_eq!(1, parsed.skipped.len());
+    }
+}
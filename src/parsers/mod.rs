@@ -0,0 +1,270 @@
+/*
+ * This file is part of the IVMS Online.
+ *
+ * @copyright 2024 © by Rafał Wrzeszcz - Wrzasq.pl.
+ */
+
+mod ivms_v1;
+mod ivms_v2;
+
+pub use crate::parsers::ivms_v1::ReportsDataParser;
+pub use crate::parsers::ivms_v2::ReportsDataV2Parser;
+
+use crate::event_filter::EventFilter;
+use crate::model::{Report, ReportSource};
+use crate::runtime_error::RuntimeError;
+use crate::timezone::ReportTimezone;
+use chrono::{DateTime, Utc};
+use glob::{MatchOptions, Pattern};
+use log::warn;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::io::Read;
+use std::sync::Arc;
+use uuid::Uuid;
+
+static MATCH_OPTIONS: MatchOptions = MatchOptions {
+    case_sensitive: true,
+    require_literal_separator: true,
+    require_literal_leading_dot: false,
+};
+
+#[derive(Clone)]
+#[doc = "S3 object being loaded."]
+pub struct ObjectSource {
+    pub bucket_name: String,
+    pub object_key: String,
+    pub version_id: Option<String>,
+    pub ingested_at: DateTime<Utc>,
+}
+
+#[derive(Clone)]
+#[doc = "Information about currently processed archive entry."]
+pub struct EntryContext {
+    pub customer_id: Uuid,
+    pub vessel_id: Uuid,
+    pub object: ObjectSource,
+    pub entry_path: String,
+    #[doc = "Filter of series rows applicable to the customer."]
+    pub events: Arc<EventFilter>,
+    #[doc = "Units assigned to vessel sensors in the catalog, keyed by field name."]
+    pub units: Arc<HashMap<String, String>>,
+    #[doc = "Timezone of the vessel, in which report dates are determined."]
+    pub timezone: ReportTimezone,
+}
+
+impl EntryContext {
+    #[doc = "Provenance of value read at given time from this entry."]
+    pub fn source_of(&self, recorded_at: DateTime<Utc>) -> ReportSource {
+        ReportSource {
+            bucket_name: self.object.bucket_name.clone(),
+            object_key: self.object.object_key.clone(),
+            version_id: self.object.version_id.clone(),
+            entry_path: self.entry_path.clone(),
+            recorded_at,
+            ingested_at: self.object.ingested_at,
+            timezone: Some(self.timezone.to_string()),
+        }
+    }
+}
+
+#[doc = "Receiver of records extracted from archive entry."]
+pub trait ReportSink {
+    fn report(&mut self, report: Report) -> Result<(), RuntimeError>;
+
+    #[doc = "Marks value that could not be turned into report record."]
+    fn skip(&mut self, reason: String);
+
+    #[doc = "Marks series row excluded by event filter."]
+    fn filter_out(&mut self);
+
+    #[doc = "Reports suspicious input that is ignored without producing any record."]
+    fn warn(&mut self, message: String) {
+        warn!("{}", message);
+    }
+}
+
+#[derive(Default, Serialize)]
+#[doc = "Records extracted from archive entries, collected in memory."]
+pub struct ParsedEntry {
+    pub reports: Vec<Report>,
+    pub skipped: Vec<String>,
+    pub filtered: usize,
+    pub warnings: Vec<String>,
+}
+
+impl ReportSink for ParsedEntry {
+    fn report(&mut self, report: Report) -> Result<(), RuntimeError> {
+        self.reports.push(report);
+
+        Ok(())
+    }
+
+    fn skip(&mut self, reason: String) {
+        self.skipped.push(reason);
+    }
+
+    fn filter_out(&mut self) {
+        self.filtered += 1;
+    }
+
+    fn warn(&mut self, message: String) {
+        self.warnings.push(message);
+    }
+}
+
+#[cfg(test)]
+#[doc = "Context of `data/reports.json` entry of fixed sync archive."]
+pub fn fixture_context(customer_id: Uuid, vessel_id: Uuid) -> EntryContext {
+    EntryContext {
+        customer_id,
+        vessel_id,
+        object: ObjectSource {
+            bucket_name: "upload".into(),
+            object_key: "v1/SYNC/sync.zip".into(),
+            version_id: Some("v1".into()),
+            ingested_at: DateTime::from_timestamp(1704499200, 0).unwrap(),
+        },
+        entry_path: "data/reports.json".into(),
+        events: Arc::default(),
+        units: Arc::default(),
+        timezone: ReportTimezone::default(),
+    }
+}
+
+#[doc = "Parser turning archive entry content into report records."]
+pub trait EntryParser: Send + Sync {
+    #[doc = "Parser name used for diagnostics."]
+    fn name(&self) -> &str;
+
+    #[doc = "Reads entry content and passes records to the sink as soon as they are parsed."]
+    fn parse(&self, context: &EntryContext, data: &mut dyn Read, sink: &mut dyn ReportSink)
+        -> Result<(), RuntimeError>;
+}
+
+#[derive(Default)]
+#[doc = "Archive entry parsers keyed by entry path glob. First matching parser handles the entry."]
+pub struct ParserRegistry {
+    parsers: Vec<(Pattern, Arc<dyn EntryParser>)>,
+}
+
+impl ParserRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    #[doc = "Registry with built-in IVMSv1 parsers."]
+    pub fn ivms_v1() -> Result<Self, RuntimeError> {
+        Self::new().register("data/reports.json", ReportsDataParser)
+    }
+
+    #[doc = "Registry with built-in IVMSv2 parsers."]
+    pub fn ivms_v2() -> Result<Self, RuntimeError> {
+        Self::new().register("data/reports.json", ReportsDataV2Parser)
+    }
+
+    pub fn register(mut self, glob: &str, parser: impl EntryParser + 'static) -> Result<Self, RuntimeError> {
+        self.parsers.push((Pattern::new(glob)?, Arc::new(parser)));
+
+        Ok(self)
+    }
+
+    pub fn find(&self, path: &str) -> Option<Arc<dyn EntryParser>> {
+        self.parsers
+            .iter()
+            .find(|(pattern, _)| pattern.matches_with(path, MATCH_OPTIONS))
+            .map(|(_, parser)| parser.clone())
+    }
+}
+
+#[derive(Default)]
+#[doc = "Parser registries of supported sync formats, keyed by object key prefix."]
+pub struct SyncFormats {
+    formats: HashMap<String, ParserRegistry>,
+}
+
+impl SyncFormats {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    #[doc = "All built-in sync formats."]
+    pub fn with_defaults() -> Result<Self, RuntimeError> {
+        Ok(Self::new()
+            .register("v1", ParserRegistry::ivms_v1()?)
+            .register("v2", ParserRegistry::ivms_v2()?))
+    }
+
+    pub fn register(mut self, prefix: &str, parsers: ParserRegistry) -> Self {
+        self.formats.insert(prefix.into(), parsers);
+
+        self
+    }
+
+    pub fn find(&self, prefix: &str) -> Option<&ParserRegistry> {
+        self.formats.get(prefix)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::parsers::{EntryContext, EntryParser, ParserRegistry, ReportSink, SyncFormats};
+    use crate::runtime_error::RuntimeError;
+    use std::io::Read;
+
+    struct TestParser(&'static str);
+
+    impl EntryParser for TestParser {
+        fn name(&self) -> &str {
+            self.0
+        }
+
+        fn parse(
+            &self,
+            _context: &EntryContext,
+            _data: &mut dyn Read,
+            _sink: &mut dyn ReportSink,
+        ) -> Result<(), RuntimeError> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn find_default_parser() {
+        let registry = ParserRegistry::ivms_v1().unwrap();
+
+        assert_eq!("IVMSv1 reports", registry.find("data/reports.json").unwrap().name());
+        assert!(registry.find("data/alarms.json").is_none());
+    }
+
+    #[test]
+    fn find_format() {
+        let formats = SyncFormats::with_defaults().unwrap();
+
+        assert_eq!(
+            "IVMSv1 reports",
+            formats.find("v1").unwrap().find("data/reports.json").unwrap().name()
+        );
+        assert_eq!(
+            "IVMSv2 reports",
+            formats.find("v2").unwrap().find("data/reports.json").unwrap().name()
+        );
+        assert!(formats.find("v3").is_none());
+    }
+
+    #[test]
+    fn find_by_glob() {
+        let registry = ParserRegistry::new()
+            .register("data/alarms.json", TestParser("alarms"))
+            .unwrap()
+            .register("data/*.json", TestParser("data"))
+            .unwrap()
+            .register("**/*.json", TestParser("any"))
+            .unwrap();
+
+        assert_eq!("alarms", registry.find("data/alarms.json").unwrap().name());
+        assert_eq!("data", registry.find("data/events.json").unwrap().name());
+        assert_eq!("any", registry.find("data/snapshots/sensors.json").unwrap().name());
+        assert!(registry.find("data/events.csv").is_none());
+    }
+}
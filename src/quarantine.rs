@@ -0,0 +1,158 @@
+/*
+ * This file is part of the IVMS Online.
+ *
+ * @copyright 2024 © by Rafał Wrzeszcz - Wrzasq.pl.
+ */
+
+use crate::runtime_error::RuntimeError;
+use async_zip::error::ZipError;
+use aws_sdk_s3::primitives::ByteStream;
+use aws_sdk_s3::Client as S3Client;
+use chrono::{DateTime, Utc};
+use log::warn;
+use serde::Serialize;
+use serde_json::to_vec;
+use std::io::ErrorKind;
+use urlencoding::encode;
+
+#[derive(Serialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+#[doc = "Loading step at which archive was rejected."]
+pub enum LoadStage {
+    ObjectKey,
+    Archive,
+    Entry,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+#[doc = "Description of sync archive rejected as malformed."]
+pub struct QuarantineManifest {
+    pub bucket_name: String,
+    pub object_key: String,
+    pub e_tag: String,
+    pub stage: LoadStage,
+    pub entry: Option<String>,
+    pub reason: String,
+    pub quarantined_at: DateTime<Utc>,
+}
+
+#[doc = "Determines loading stage at which error was caused by malformed input. Returns `None` for transient errors."]
+pub fn load_stage_of(error: &RuntimeError) -> Option<LoadStage> {
+    match error {
+        RuntimeError::UuidError(_) => Some(LoadStage::ObjectKey),
+        // I/O errors may be caused by S3 stream interruption, only broken data can't be recovered
+        RuntimeError::ZipError(ZipError::UpstreamReadError(io)) | RuntimeError::IoError(io)
+            if !matches!(io.kind(), ErrorKind::InvalidData | ErrorKind::UnexpectedEof) =>
+        {
+            None
+        }
+        RuntimeError::ZipError(_)
+        | RuntimeError::IoError(_)
+        | RuntimeError::TarError(_)
+        | RuntimeError::UnrecognizedInput
+        | RuntimeError::EncryptedArchive
+        | RuntimeError::EntryCountLimitExceeded(_)
+        | RuntimeError::ArchiveSizeLimitExceeded(_) => Some(LoadStage::Archive),
+        RuntimeError::SerializationError(_)
+        | RuntimeError::NestedArchive(_)
+        | RuntimeError::EntrySizeLimitExceeded(_)
+        | RuntimeError::CompressionRatioLimitExceeded(_) => Some(LoadStage::Entry),
+        _ => None,
+    }
+}
+
+#[doc = "Copies rejected object under quarantine prefix along with error manifest. Returns quarantined copy key."]
+pub async fn quarantine(s3: &S3Client, prefix: &str, manifest: &QuarantineManifest) -> Result<String, RuntimeError> {
+    let quarantine_key = format!("{prefix}{}", manifest.object_key);
+
+    warn!(
+        "Quarantining S3 object {} as {}: {}",
+        manifest.object_key, quarantine_key, manifest.reason
+    );
+
+    s3.copy_object()
+        .copy_source(format!("{}/{}", manifest.bucket_name, encode(&manifest.object_key)))
+        .bucket(manifest.bucket_name.as_str())
+        .key(quarantine_key.as_str())
+        .send()
+        .await?;
+    s3.put_object()
+        .bucket(manifest.bucket_name.as_str())
+        .key(format!("{quarantine_key}.error.json"))
+        .content_type("application/json")
+        .body(ByteStream::from(to_vec(manifest)?))
+        .send()
+        .await?;
+
+    Ok(quarantine_key)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::quarantine::{load_stage_of, LoadStage};
+    use crate::runtime_error::RuntimeError;
+    use async_zip::error::ZipError;
+    use serde_json::from_str;
+    use std::io::{Error, ErrorKind};
+    use uuid::Uuid;
+
+    #[test]
+    fn malformed_input_stage() {
+        assert_eq!(
+            Some(LoadStage::ObjectKey),
+            load_stage_of(&RuntimeError::from(Uuid::parse_str("invalid").unwrap_err()))
+        );
+        assert_eq!(
+            Some(LoadStage::Archive),
+            load_stage_of(&RuntimeError::from(ZipError::CRC32CheckError))
+        );
+        assert_eq!(
+            Some(LoadStage::Archive),
+            load_stage_of(&RuntimeError::from(ZipError::UpstreamReadError(Error::from(
+                ErrorKind::InvalidData
+            ))))
+        );
+        assert_eq!(
+            Some(LoadStage::Entry),
+            load_stage_of(&RuntimeError::from(from_str::<u32>("{").unwrap_err()))
+        );
+    }
+
+    #[test]
+    fn limits_violation_stage() {
+        assert_eq!(
+            Some(LoadStage::Archive),
+            load_stage_of(&RuntimeError::EntryCountLimitExceeded(10))
+        );
+        assert_eq!(Some(LoadStage::Archive), load_stage_of(&RuntimeError::EncryptedArchive));
+        assert_eq!(
+            Some(LoadStage::Archive),
+            load_stage_of(&RuntimeError::UnrecognizedInput)
+        );
+        assert_eq!(
+            Some(LoadStage::Archive),
+            load_stage_of(&RuntimeError::from(Error::from(ErrorKind::InvalidData)))
+        );
+        assert_eq!(
+            Some(LoadStage::Entry),
+            load_stage_of(&RuntimeError::NestedArchive("data/inner.zip".into()))
+        );
+        assert_eq!(
+            Some(LoadStage::Entry),
+            load_stage_of(&RuntimeError::CompressionRatioLimitExceeded(100))
+        );
+    }
+
+    #[test]
+    fn transient_error_stage() {
+        assert!(
+            load_stage_of(&RuntimeError::from(ZipError::UpstreamReadError(Error::from(
+                ErrorKind::ConnectionReset
+            ))))
+            .is_none()
+        );
+        assert!(load_stage_of(&RuntimeError::from(Error::from(ErrorKind::TimedOut))).is_none());
+        assert!(load_stage_of(&RuntimeError::UnprocessedItems(1)).is_none());
+    }
+}
@@ -4,9 +4,86 @@
  * @copyright 2024 © by Rafał Wrzeszcz - Wrzasq.pl.
  */
 
+use crate::model::{Report, VesselReportPageToken};
+use aws_sdk_dynamodb::types::AttributeValue::S;
+use aws_sdk_dynamodb::Client as DynamoDbClient;
+use chrono::NaiveDate;
+use serde_dynamo::to_item;
+use wrzasqpl_commons_aws::{DaoError, DynamoDbResultsPage};
+
+#[doc = "Reports-specific DynamoDB operations not covered by generic DAO."]
+pub struct ReportDao {
+    client: DynamoDbClient,
+    table_name: String,
+}
+
+impl ReportDao {
+    pub fn new(client: DynamoDbClient, table_name: String) -> Self {
+        Self { client, table_name }
+    }
+
+    #[doc = "Queries `vesselReports` index for fields of single vessel report."]
+    pub async fn query_report(
+        &self,
+        hash_key: String,
+        report_name: String,
+        page_token: Option<VesselReportPageToken>,
+    ) -> Result<DynamoDbResultsPage<Report, VesselReportPageToken>, DaoError> {
+        self.query_vessel_reports(hash_key, "#range = :name", vec![(":name", report_name)], page_token)
+            .await
+    }
+
+    #[doc = "Queries `vesselReports` index for fields of all vessel reports within given days (both inclusive)."]
+    pub async fn query_reports(
+        &self,
+        hash_key: String,
+        from: NaiveDate,
+        to: NaiveDate,
+        page_token: Option<VesselReportPageToken>,
+    ) -> Result<DynamoDbResultsPage<Report, VesselReportPageToken>, DaoError> {
+        // report names are prefixed with the day, so the bare next day sorts right after all of the last day reports
+        let until = to.succ_opt().unwrap_or(to);
+
+        self.query_vessel_reports(
+            hash_key,
+            "#range BETWEEN :from AND :to",
+            vec![(":from", from.to_string()), (":to", until.to_string())],
+            page_token,
+        )
+        .await
+    }
+
+    async fn query_vessel_reports(
+        &self,
+        hash_key: String,
+        range_condition: &str,
+        range_values: Vec<(&str, String)>,
+        page_token: Option<VesselReportPageToken>,
+    ) -> Result<DynamoDbResultsPage<Report, VesselReportPageToken>, DaoError> {
+        range_values
+            .into_iter()
+            .fold(
+                self.client
+                    .query()
+                    .table_name(self.table_name.as_str())
+                    .index_name("vesselReports")
+                    .key_condition_expression(format!("#hash = :hash AND {range_condition}"))
+                    .expression_attribute_names("#hash", "customerAndVesselId")
+                    .expression_attribute_names("#range", "reportName")
+                    .expression_attribute_values(":hash", S(hash_key)),
+                |request, (name, value)| request.expression_attribute_values(name, S(value)),
+            )
+            .set_exclusive_start_key(page_token.map(to_item).map_or(Ok(None), |token| token.map(Some))?)
+            .send()
+            .await?
+            .try_into()
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use crate::model::{hash_key_of, sort_key_of, Report, ReportKey};
+    use crate::model::{hash_key_of, sort_key_of, Report, ReportKey, ReportValue, VesselReportPageToken};
+    use crate::report_dao::ReportDao;
     use aws_config::load_defaults;
     use aws_sdk_dynamodb::config::Builder;
     use aws_sdk_dynamodb::operation::put_item::{PutItemError, PutItemOutput};
@@ -18,6 +95,7 @@ mod tests {
     use aws_smithy_runtime_api::client::behavior_version::BehaviorVersion;
     use aws_smithy_runtime_api::client::orchestrator::HttpResponse;
     use aws_smithy_runtime_api::client::result::SdkError;
+    use chrono::NaiveDate;
     use std::env::var;
     use std::future::join;
     use std::sync::atomic::{AtomicUsize, Ordering};
@@ -29,6 +107,7 @@ mod tests {
     struct DynamoDbTestContext {
         client: Box<Client>,
         dao: Box<DynamoDbDao>,
+        report_dao: Box<ReportDao>,
         table_name: String,
     }
 
@@ -120,7 +199,8 @@ mod tests {
 
             let context = DynamoDbTestContext {
                 client: Box::new(client.clone()),
-                dao: Box::new(DynamoDbDao::new(client, table_name.clone())),
+                dao: Box::new(DynamoDbDao::new(client.clone(), table_name.clone())),
+                report_dao: Box::new(ReportDao::new(client, table_name.clone())),
                 table_name: table_name.clone(),
             };
 
@@ -157,8 +237,11 @@ mod tests {
                 vessel_id: ID_2,
                 report_name: REPORT_NAME_1.to_string(),
                 field_name: FIELD_NAME_1.to_string(),
-                value: "123".into(),
+                value: ReportValue::Number(123.0),
                 label: "Test_Count".into(),
+                source: None,
+                unit: None,
+                normalized: None,
             })
             .await?;
 
@@ -171,7 +254,7 @@ mod tests {
             .send()
             .await
             .unwrap();
-        assert_eq!("123", report.item.as_ref().unwrap()["value"].as_s().unwrap());
+        assert_eq!("123", report.item.as_ref().unwrap()["value"].as_n().unwrap());
         assert_eq!("Test_Count", report.item.as_ref().unwrap()["label"].as_s().unwrap());
 
         Ok(())
@@ -188,7 +271,49 @@ mod tests {
             })
             .await?
             .unwrap();
-        assert_eq!("100", report.value);
+        assert_eq!(ReportValue::Number(100.0), report.value);
+
+        Ok(())
+    }
+
+    #[test_context(DynamoDbTestContext)]
+    #[tokio_test]
+    async fn get_report_typed(ctx: &DynamoDbTestContext) -> Result<(), DaoError> {
+        ctx.dao
+            .save(&mut Report {
+                customer_id: ID_0,
+                vessel_id: ID_2,
+                report_name: REPORT_NAME_1.to_string(),
+                field_name: FIELD_NAME_1.to_string(),
+                value: ReportValue::Bool(true),
+                label: "Test_Flag".into(),
+                source: None,
+                unit: None,
+                normalized: None,
+            })
+            .await?;
+        ctx.create_record(&ID_0, &ID_2, REPORT_NAME_0, FIELD_NAME_0, "on", "Test_Text")
+            .await
+            .unwrap();
+
+        let flag = ctx
+            .dao
+            .load::<Report>(ReportKey {
+                customer_and_vessel_id: hash_key_of(&ID_0, &ID_2),
+                report_key: sort_key_of(&REPORT_NAME_1.into(), &FIELD_NAME_1.into()),
+            })
+            .await?
+            .unwrap();
+        let text = ctx
+            .dao
+            .load::<Report>(ReportKey {
+                customer_and_vessel_id: hash_key_of(&ID_0, &ID_2),
+                report_key: sort_key_of(&REPORT_NAME_0.into(), &FIELD_NAME_0.into()),
+            })
+            .await?
+            .unwrap();
+        assert_eq!(ReportValue::Bool(true), flag.value);
+        assert_eq!(ReportValue::Text("on".into()), text.value);
 
         Ok(())
     }
@@ -292,6 +417,113 @@ mod tests {
         Ok(())
     }
 
+    #[test_context(DynamoDbTestContext)]
+    #[tokio_test]
+    async fn query_report(ctx: &DynamoDbTestContext) -> Result<(), DaoError> {
+        let results = ctx
+            .report_dao
+            .query_report(hash_key_of(&ID_0, &ID_1), REPORT_NAME_0.into(), None)
+            .await?;
+
+        assert_eq!(2, results.items.len());
+        assert!(results.items.iter().all(|item| item.report_name == REPORT_NAME_0));
+        assert!(results.last_evaluated_key.is_none());
+
+        Ok(())
+    }
+
+    #[test_context(DynamoDbTestContext)]
+    #[tokio_test]
+    async fn query_report_page(ctx: &DynamoDbTestContext) -> Result<(), DaoError> {
+        let first = ctx
+            .report_dao
+            .query_report(hash_key_of(&ID_0, &ID_1), REPORT_NAME_0.into(), None)
+            .await?;
+        let results = ctx
+            .report_dao
+            .query_report(
+                hash_key_of(&ID_0, &ID_1),
+                REPORT_NAME_0.into(),
+                Some(VesselReportPageToken {
+                    customer_and_vessel_id: hash_key_of(&ID_0, &ID_1),
+                    report_name: REPORT_NAME_0.into(),
+                    report_key: sort_key_of(&REPORT_NAME_0.into(), &first.items[0].field_name),
+                }),
+            )
+            .await?;
+
+        assert_eq!(1, results.items.len());
+        assert_eq!(REPORT_NAME_0, results.items[0].report_name);
+        assert_ne!(first.items[0].field_name, results.items[0].field_name);
+
+        Ok(())
+    }
+
+    #[test_context(DynamoDbTestContext)]
+    #[tokio_test]
+    async fn query_report_other_name(ctx: &DynamoDbTestContext) -> Result<(), DaoError> {
+        // REPORT_NAME_1 exists, but only for another vessel
+        let results = ctx
+            .report_dao
+            .query_report(hash_key_of(&ID_0, &ID_1), REPORT_NAME_1.into(), None)
+            .await?;
+
+        assert!(results.items.is_empty());
+        assert!(results.last_evaluated_key.is_none());
+
+        Ok(())
+    }
+
+    #[test_context(DynamoDbTestContext)]
+    #[tokio_test]
+    async fn query_reports_range(ctx: &DynamoDbTestContext) -> Result<(), DaoError> {
+        let (res1, res2, res3, res4) = join!(
+            ctx.create_record(&ID_0, &ID_2, "2024-02-29.daily", FIELD_NAME_0, "1", "Test_Count"),
+            ctx.create_record(&ID_0, &ID_2, "2024-03-01.daily", FIELD_NAME_0, "2", "Test_Count"),
+            ctx.create_record(&ID_0, &ID_2, "2024-03-31.daily", FIELD_NAME_0, "3", "Test_Count"),
+            ctx.create_record(&ID_0, &ID_2, "2024-04-01.daily", FIELD_NAME_0, "4", "Test_Count"),
+        )
+        .await;
+        res1.unwrap();
+        res2.unwrap();
+        res3.unwrap();
+        res4.unwrap();
+
+        let results = ctx
+            .report_dao
+            .query_reports(
+                hash_key_of(&ID_0, &ID_2),
+                NaiveDate::from_ymd_opt(2024, 3, 1).unwrap(),
+                NaiveDate::from_ymd_opt(2024, 3, 31).unwrap(),
+                None,
+            )
+            .await?;
+
+        assert_eq!(2, results.items.len());
+        assert_eq!("2024-03-01.daily", results.items[0].report_name);
+        assert_eq!("2024-03-31.daily", results.items[1].report_name);
+
+        Ok(())
+    }
+
+    #[test_context(DynamoDbTestContext)]
+    #[tokio_test]
+    async fn query_reports_range_empty(ctx: &DynamoDbTestContext) -> Result<(), DaoError> {
+        let results = ctx
+            .report_dao
+            .query_reports(
+                hash_key_of(&ID_0, &ID_1),
+                NaiveDate::from_ymd_opt(2024, 3, 1).unwrap(),
+                NaiveDate::from_ymd_opt(2024, 3, 31).unwrap(),
+                None,
+            )
+            .await?;
+
+        assert!(results.items.is_empty());
+
+        Ok(())
+    }
+
     impl DynamoDbTestContext {
         async fn create_record(
             &self,
@@ -0,0 +1,289 @@
+/*
+ * This file is part of the IVMS Online.
+ *
+ * @copyright 2024 © by Rafał Wrzeszcz - Wrzasq.pl.
+ */
+
+use crate::loader::{backoff_delay, sync_key_of, LoadOutcome, LoaderConfig};
+use crate::model::{hash_key_of, Ingestion};
+use crate::runtime_error::RuntimeError;
+use aws_sdk_dynamodb::types::AttributeValue;
+use aws_sdk_dynamodb::types::AttributeValue::S;
+use aws_sdk_dynamodb::Client as DynamoDbClient;
+use futures::{stream, StreamExt, TryStreamExt};
+use log::{trace, warn};
+use std::collections::HashMap;
+use tokio::time::sleep;
+use uuid::Uuid;
+use wrzasqpl_commons_aws::DynamoDbDao;
+
+static DELETES_CONCURRENCY: usize = 25;
+
+// condition matching report items loaded from given object
+struct SourceCondition {
+    expression: String,
+    names: HashMap<String, String>,
+    values: HashMap<String, AttributeValue>,
+}
+
+impl SourceCondition {
+    fn new(bucket_name: &str, object_key: &str, version_id: Option<&str>) -> Self {
+        let mut condition = Self {
+            expression: "#source.#bucketName = :bucketName AND #source.#objectKey = :objectKey".into(),
+            names: HashMap::from([
+                ("#source".into(), "source".into()),
+                ("#bucketName".into(), "bucketName".into()),
+                ("#objectKey".into(), "objectKey".into()),
+            ]),
+            values: HashMap::from([
+                (":bucketName".into(), S(bucket_name.into())),
+                (":objectKey".into(), S(object_key.into())),
+            ]),
+        };
+
+        // without version all versions of the key are retracted
+        if let Some(version_id) = version_id {
+            condition.expression.push_str(" AND #source.#versionId = :versionId");
+            condition.names.insert("#versionId".into(), "versionId".into());
+            condition.values.insert(":versionId".into(), S(version_id.into()));
+        }
+
+        condition
+    }
+}
+
+fn is_ingestion_of(ingestion: &Ingestion, bucket_name: &str, object_key: &str, version_id: Option<&str>) -> bool {
+    ingestion.bucket_name == bucket_name
+        && ingestion.object_key == object_key
+        && version_id.is_none_or(|version_id| ingestion.version_id.as_deref() == Some(version_id))
+}
+
+#[doc = "Removes report fields loaded from removed object, together with its ledger entries, so that the same content can \
+be loaded again after re-upload."]
+pub async fn retract_object(
+    dynamodb: &DynamoDbClient,
+    ledger: &DynamoDbDao,
+    config: &LoaderConfig,
+    bucket_name: &str,
+    object_key: &str,
+    version_id: Option<&str>,
+) -> Result<LoadOutcome, RuntimeError> {
+    // nothing is loaded from quarantined copies and unrecognized keys
+    if object_key.starts_with(config.quarantine_prefix.as_str()) {
+        return Ok(LoadOutcome::Ignored);
+    }
+    let Some((_, customer_id, vessel_id)) = sync_key_of(object_key) else {
+        return Ok(LoadOutcome::Ignored);
+    };
+    let (Ok(customer_id), Ok(vessel_id)) = (Uuid::parse_str(customer_id), Uuid::parse_str(vessel_id)) else {
+        return Ok(LoadOutcome::Ignored);
+    };
+    let hash_key = hash_key_of(&customer_id, &vessel_id);
+
+    let removed = retract_reports(
+        dynamodb,
+        config.table_name.as_str(),
+        config.write_attempts,
+        hash_key.as_str(),
+        &SourceCondition::new(bucket_name, object_key, version_id),
+    )
+    .await?;
+
+    let mut ingestions = vec![];
+    let mut page_token = None;
+    loop {
+        let page = ledger.query::<Ingestion, _>(hash_key.as_str(), page_token).await?;
+        ingestions.extend(
+            page.items
+                .into_iter()
+                .filter(|ingestion| is_ingestion_of(ingestion, bucket_name, object_key, version_id)),
+        );
+
+        page_token = page.last_evaluated_key;
+        if page_token.is_none() {
+            break;
+        }
+    }
+    for ingestion in &ingestions {
+        ledger.delete_item(ingestion).await?;
+    }
+
+    Ok(LoadOutcome::Retracted {
+        removed,
+        ingestions: ingestions.len(),
+    })
+}
+
+async fn retract_reports(
+    dynamodb: &DynamoDbClient,
+    table_name: &str,
+    write_attempts: u32,
+    hash_key: &str,
+    condition: &SourceCondition,
+) -> Result<usize, RuntimeError> {
+    let mut removed = 0;
+    let mut page_token = None;
+
+    loop {
+        let page = dynamodb
+            .query()
+            .table_name(table_name)
+            .key_condition_expression("#hash = :hash")
+            .filter_expression(condition.expression.as_str())
+            .projection_expression("#hash, #range")
+            .set_expression_attribute_names(Some(condition.names.clone()))
+            .expression_attribute_names("#hash", "customerAndVesselId")
+            .expression_attribute_names("#range", "reportKey")
+            .set_expression_attribute_values(Some(condition.values.clone()))
+            .expression_attribute_values(":hash", S(hash_key.into()))
+            .set_exclusive_start_key(page_token)
+            .send()
+            .await?;
+
+        // query page may hold thousands of keys, so deletes are throttled on our side as well
+        removed += stream::iter(page.items.unwrap_or_default())
+            .map(|key| delete_report(dynamodb, table_name, write_attempts, key, condition))
+            .buffer_unordered(DELETES_CONCURRENCY)
+            .try_fold(0, |removed, deleted| async move { Ok(removed + usize::from(deleted)) })
+            .await?;
+
+        page_token = page.last_evaluated_key;
+        if page_token.is_none() {
+            return Ok(removed);
+        }
+    }
+}
+
+// field may have been overwritten by another upload since it was queried, then it's kept
+async fn delete_report(
+    dynamodb: &DynamoDbClient,
+    table_name: &str,
+    write_attempts: u32,
+    key: HashMap<String, AttributeValue>,
+    condition: &SourceCondition,
+) -> Result<bool, RuntimeError> {
+    let mut attempt = 1;
+
+    loop {
+        let error = match dynamodb
+            .delete_item()
+            .table_name(table_name)
+            .set_key(Some(key.clone()))
+            .condition_expression(condition.expression.as_str())
+            .set_expression_attribute_names(Some(condition.names.clone()))
+            .set_expression_attribute_values(Some(condition.values.clone()))
+            .send()
+            .await
+        {
+            Ok(_) => return Ok(true),
+            Err(error) => error,
+        };
+
+        match error.as_service_error() {
+            Some(service_error) if service_error.is_conditional_check_failed_exception() => {
+                trace!("Report field was overwritten in the meantime: {:?}", key);
+                return Ok(false);
+            }
+            // retraction can be safely repeated, so after giving up the remaining fields are removed on redelivery
+            Some(service_error)
+                if attempt < write_attempts
+                    && (service_error.is_provisioned_throughput_exceeded_exception()
+                        || service_error.is_request_limit_exceeded()) =>
+            {
+                let delay = backoff_delay(attempt);
+                warn!(
+                    "Report field delete was throttled, retrying in {}ms.",
+                    delay.as_millis()
+                );
+                sleep(delay).await;
+                attempt += 1;
+            }
+            _ => return Err(error.into()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::model::Ingestion;
+    use crate::retraction::{is_ingestion_of, SourceCondition};
+    use aws_sdk_dynamodb::types::AttributeValue::S;
+    use chrono::Utc;
+    use uuid::{uuid, Uuid};
+
+    const CUSTOMER_ID: Uuid = uuid!("00000000-0000-0000-0000-000000000000");
+    const VESSEL_ID: Uuid = uuid!("00000000-0000-0000-0000-000000000001");
+
+    fn ingestion(object_key: &str, version_id: Option<&str>) -> Ingestion {
+        Ingestion {
+            customer_id: CUSTOMER_ID,
+            vessel_id: VESSEL_ID,
+            bucket_name: "upload".into(),
+            object_key: object_key.into(),
+            e_tag: "abc".into(),
+            version_id: version_id.map(String::from),
+            saved: 1,
+            skipped: 0,
+            filtered: 0,
+            stale: 0,
+            quarantine_key: None,
+            quarantine_reason: None,
+            finished_at: Utc::now(),
+        }
+    }
+
+    #[test]
+    fn object_condition() {
+        let condition = SourceCondition::new("upload", "v1/SYNC/sync.zip", None);
+
+        assert_eq!(
+            "#source.#bucketName = :bucketName AND #source.#objectKey = :objectKey",
+            condition.expression
+        );
+        assert_eq!(3, condition.names.len());
+        assert_eq!(Some(&S("v1/SYNC/sync.zip".into())), condition.values.get(":objectKey"));
+    }
+
+    #[test]
+    fn object_version_condition() {
+        let condition = SourceCondition::new("upload", "v1/SYNC/sync.zip", Some("v2"));
+
+        assert!(condition.expression.ends_with(" AND #source.#versionId = :versionId"));
+        assert_eq!(Some(&"versionId".to_string()), condition.names.get("#versionId"));
+        assert_eq!(Some(&S("v2".into())), condition.values.get(":versionId"));
+    }
+
+    #[test]
+    fn match_ingestions() {
+        assert!(is_ingestion_of(
+            &ingestion("v1/SYNC/sync.zip", Some("v1")),
+            "upload",
+            "v1/SYNC/sync.zip",
+            None
+        ));
+        assert!(is_ingestion_of(
+            &ingestion("v1/SYNC/sync.zip", Some("v1")),
+            "upload",
+            "v1/SYNC/sync.zip",
+            Some("v1")
+        ));
+        assert!(!is_ingestion_of(
+            &ingestion("v1/SYNC/sync.zip", Some("v1")),
+            "upload",
+            "v1/SYNC/sync.zip",
+            Some("v2")
+        ));
+        assert!(!is_ingestion_of(
+            &ingestion("v1/SYNC/sync.zip", None),
+            "upload",
+            "v1/SYNC/other.zip",
+            None
+        ));
+        assert!(!is_ingestion_of(
+            &ingestion("v1/SYNC/sync.zip", None),
+            "archive",
+            "v1/SYNC/sync.zip",
+            None
+        ));
+    }
+}
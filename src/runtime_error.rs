@@ -5,17 +5,29 @@
  */
 
 use async_zip::error::ZipError;
+use aws_sdk_dynamodb::operation::batch_get_item::BatchGetItemError;
 use aws_sdk_dynamodb::operation::batch_write_item::BatchWriteItemError;
+use aws_sdk_dynamodb::operation::delete_item::DeleteItemError;
+use aws_sdk_dynamodb::operation::put_item::PutItemError;
+use aws_sdk_dynamodb::operation::query::QueryError;
+use aws_sdk_dynamodb::operation::scan::ScanError;
+use aws_sdk_dynamodb::operation::update_item::UpdateItemError;
+use aws_sdk_s3::operation::copy_object::CopyObjectError;
 use aws_sdk_s3::operation::get_object::GetObjectError;
+use aws_sdk_s3::operation::list_objects_v2::ListObjectsV2Error;
+use aws_sdk_s3::operation::put_object::PutObjectError;
 use aws_smithy_runtime_api::client::orchestrator::HttpResponse;
 use aws_smithy_runtime_api::client::result::SdkError;
 use aws_smithy_types::error::operation::BuildError;
+use glob::PatternError;
 use serde_dynamo::Error as DynamoDbSerializationError;
 use serde_json::Error as SerializationError;
 use std::env::VarError;
 use std::fmt::{Debug, Display, Formatter, Result};
+use std::io::Error as IoError;
 use std::num::{ParseFloatError, ParseIntError};
 use thiserror::Error;
+use tokio::task::JoinError;
 use uuid::Error as UuidError;
 use wrzasqpl_commons_aws::DaoError;
 
@@ -24,19 +36,75 @@ pub enum RuntimeError {
     ClientConfigLoadingError(#[from] VarError),
     Dao(#[from] DaoError),
     MalformedS3Event,
+    InvalidFetchRequest,
     SerializationError(#[from] SerializationError),
     DynamoDbSerializationError(#[from] DynamoDbSerializationError),
     ParseIntError(#[from] ParseIntError),
     ParseFloatError(#[from] ParseFloatError),
     ZipError(#[from] ZipError),
     GetObjectError(#[from] SdkError<GetObjectError, HttpResponse>),
+    CopyObjectError(#[from] SdkError<CopyObjectError, HttpResponse>),
+    PutObjectError(#[from] SdkError<PutObjectError, HttpResponse>),
+    ListObjectsError(#[from] SdkError<ListObjectsV2Error, HttpResponse>),
+    BatchGetItemOperation(#[from] SdkError<BatchGetItemError, HttpResponse>),
     BatchWriteItemOperation(#[from] SdkError<BatchWriteItemError, HttpResponse>),
+    PutItemOperation(#[from] SdkError<PutItemError, HttpResponse>),
+    QueryOperation(#[from] SdkError<QueryError, HttpResponse>),
+    DeleteItemOperation(#[from] SdkError<DeleteItemError, HttpResponse>),
+    UpdateItemOperation(#[from] SdkError<UpdateItemError, HttpResponse>),
+    UnprocessedItems(usize),
+    ScanOperation(#[from] SdkError<ScanError, HttpResponse>),
     BuildError(#[from] BuildError),
     UuidError(#[from] UuidError),
+    PatternError(#[from] PatternError),
+    EntryStreamClosed,
+    TaskError(#[from] JoinError),
+    ArchiveSizeLimitExceeded(u64),
+    EntrySizeLimitExceeded(u64),
+    EntryCountLimitExceeded(usize),
+    CompressionRatioLimitExceeded(u64),
+    EncryptedArchive,
+    NestedArchive(String),
+    IoError(#[from] IoError),
+    TarError(String),
+    UnrecognizedInput,
+    InvalidArguments(String),
+    InvalidTimezone(String),
 }
 
 impl Display for RuntimeError {
     fn fmt(&self, formatter: &mut Formatter<'_>) -> Result {
-        write!(formatter, "{self:?}")
+        match self {
+            Self::UnprocessedItems(count) => write!(formatter, "UnprocessedItems: {count} items were lost"),
+            Self::ArchiveSizeLimitExceeded(limit) => {
+                write!(
+                    formatter,
+                    "ArchiveSizeLimitExceeded: archive exceeds {limit} uncompressed bytes"
+                )
+            }
+            Self::EntrySizeLimitExceeded(limit) => {
+                write!(
+                    formatter,
+                    "EntrySizeLimitExceeded: entry exceeds {limit} uncompressed bytes"
+                )
+            }
+            Self::EntryCountLimitExceeded(limit) => {
+                write!(
+                    formatter,
+                    "EntryCountLimitExceeded: archive has more than {limit} entries"
+                )
+            }
+            Self::CompressionRatioLimitExceeded(limit) => {
+                write!(
+                    formatter,
+                    "CompressionRatioLimitExceeded: entry is compressed more than {limit} times"
+                )
+            }
+            Self::NestedArchive(entry) => write!(formatter, "NestedArchive: {entry} is an archive"),
+            Self::TarError(reason) => write!(formatter, "TarError: {reason}"),
+            Self::InvalidArguments(reason) => write!(formatter, "InvalidArguments: {reason}"),
+            Self::InvalidTimezone(timezone) => write!(formatter, "InvalidTimezone: {timezone} is not a valid timezone"),
+            _ => write!(formatter, "{self:?}"),
+        }
     }
 }
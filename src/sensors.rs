@@ -0,0 +1,296 @@
+/*
+ * This file is part of the IVMS Online.
+ *
+ * @copyright 2024 © by Rafał Wrzeszcz - Wrzasq.pl.
+ */
+
+use crate::loader::backoff_delay;
+use crate::model::{hash_key_of, Report, Sensor};
+use crate::runtime_error::RuntimeError;
+use aws_sdk_dynamodb::types::AttributeValue::S;
+use aws_sdk_dynamodb::types::{AttributeValue, KeysAndAttributes};
+use aws_sdk_dynamodb::Client as DynamoDbClient;
+use chrono::{DateTime, SecondsFormat, Utc};
+use futures::{stream, StreamExt, TryStreamExt};
+use std::collections::HashMap;
+use tokio::time::sleep;
+use uuid::Uuid;
+use wrzasqpl_commons_aws::DynamoDbDao;
+
+static UPDATES_CONCURRENCY: usize = 25;
+static LOOKUP_CHUNK_SIZE: usize = 100;
+static LOOKUP_ATTEMPTS: u32 = 3;
+
+// moves one end of the seen period, the other one is set only for new sensors
+static FIRST_SEEN_UPDATE: &str = "SET #customerId = :customerId, #vesselId = :vesselId, \
+#label = if_not_exists(#label, :label), #firstSeenAt = :firstSeenAt, \
+#lastSeenAt = if_not_exists(#lastSeenAt, :lastSeenAt)";
+static FIRST_SEEN_CONDITION: &str = "attribute_not_exists(#firstSeenAt) OR #firstSeenAt > :firstSeenAt";
+static LAST_SEEN_UPDATE: &str = "SET #customerId = :customerId, #vesselId = :vesselId, #label = :label, \
+#lastSeenAt = :lastSeenAt, #firstSeenAt = if_not_exists(#firstSeenAt, :firstSeenAt)";
+static LAST_SEEN_CONDITION: &str = "attribute_not_exists(#lastSeenAt) OR #lastSeenAt < :lastSeenAt";
+
+#[derive(Debug, PartialEq)]
+struct SeenSensor {
+    label: String,
+    first_seen_at: DateTime<Utc>,
+    last_seen_at: DateTime<Utc>,
+}
+
+#[derive(Default)]
+#[doc = "Sensors encountered in processed object, merged into vessel sensor catalog once it's loaded."]
+pub struct SensorsSeen {
+    sensors: HashMap<String, SeenSensor>,
+}
+
+impl SensorsSeen {
+    pub fn record(&mut self, report: &Report) {
+        // reading time is known only from the source
+        let Some(source) = &report.source else {
+            return;
+        };
+
+        self.sensors
+            .entry(report.field_name.clone())
+            .and_modify(|sensor| {
+                sensor.first_seen_at = sensor.first_seen_at.min(source.recorded_at);
+                // label of the most recent reading is kept
+                if source.recorded_at >= sensor.last_seen_at {
+                    sensor.last_seen_at = source.recorded_at;
+                    sensor.label = report.label.clone();
+                }
+            })
+            .or_insert_with(|| SeenSensor {
+                label: report.label.clone(),
+                first_seen_at: source.recorded_at,
+                last_seen_at: source.recorded_at,
+            });
+    }
+
+    #[doc = "Merges seen sensors into the catalog - seen period is only extended, so objects can be loaded in any order."]
+    pub async fn save(
+        &self,
+        client: &DynamoDbClient,
+        table_name: &str,
+        customer_id: &Uuid,
+        vessel_id: &Uuid,
+    ) -> Result<(), RuntimeError> {
+        stream::iter(&self.sensors)
+            .map(|(field_name, sensor)| async move {
+                let key = HashMap::from([
+                    ("customerAndVesselId".into(), S(hash_key_of(customer_id, vessel_id))),
+                    ("fieldName".into(), S(field_name.clone())),
+                ]);
+                let values = HashMap::from([
+                    (":customerId".into(), S(customer_id.to_string())),
+                    (":vesselId".into(), S(vessel_id.to_string())),
+                    (":label".into(), S(sensor.label.clone())),
+                    (":firstSeenAt".into(), S(timestamp_of(&sensor.first_seen_at))),
+                    (":lastSeenAt".into(), S(timestamp_of(&sensor.last_seen_at))),
+                ]);
+
+                update_sensor(
+                    client,
+                    table_name,
+                    &key,
+                    &values,
+                    FIRST_SEEN_UPDATE,
+                    FIRST_SEEN_CONDITION,
+                )
+                .await?;
+                update_sensor(client, table_name, &key, &values, LAST_SEEN_UPDATE, LAST_SEEN_CONDITION).await
+            })
+            .buffer_unordered(UPDATES_CONCURRENCY)
+            .try_collect()
+            .await
+    }
+}
+
+// timestamps are stored in fixed format, so that they can be compared as strings
+fn timestamp_of(time: &DateTime<Utc>) -> String {
+    time.to_rfc3339_opts(SecondsFormat::AutoSi, true)
+}
+
+async fn update_sensor(
+    client: &DynamoDbClient,
+    table_name: &str,
+    key: &HashMap<String, AttributeValue>,
+    values: &HashMap<String, AttributeValue>,
+    update: &str,
+    condition: &str,
+) -> Result<(), RuntimeError> {
+    match client
+        .update_item()
+        .table_name(table_name)
+        .set_key(Some(key.clone()))
+        .update_expression(update)
+        .condition_expression(condition)
+        .expression_attribute_names("#customerId", "customerId")
+        .expression_attribute_names("#vesselId", "vesselId")
+        .expression_attribute_names("#label", "label")
+        .expression_attribute_names("#firstSeenAt", "firstSeenAt")
+        .expression_attribute_names("#lastSeenAt", "lastSeenAt")
+        .set_expression_attribute_values(Some(values.clone()))
+        .send()
+        .await
+    {
+        Ok(_) => Ok(()),
+        // catalog already covers given period
+        Err(error)
+            if error
+                .as_service_error()
+                .is_some_and(|error| error.is_conditional_check_failed_exception()) =>
+        {
+            Ok(())
+        }
+        Err(error) => Err(error.into()),
+    }
+}
+
+#[doc = "Canonical names of given vessel sensors that have one, keyed by field name."]
+pub async fn canonical_names_of(
+    client: &DynamoDbClient,
+    table_name: &str,
+    hash_key: &str,
+    field_names: Vec<String>,
+) -> Result<HashMap<String, String>, RuntimeError> {
+    let mut names = HashMap::new();
+
+    for chunk in field_names.chunks(LOOKUP_CHUNK_SIZE) {
+        let mut pending = chunk
+            .iter()
+            .map(|field_name| {
+                HashMap::from([
+                    ("customerAndVesselId".into(), S(hash_key.into())),
+                    ("fieldName".into(), S(field_name.clone())),
+                ])
+            })
+            .collect::<Vec<HashMap<String, AttributeValue>>>();
+        let mut attempt = 1;
+
+        while !pending.is_empty() {
+            let response = client
+                .batch_get_item()
+                .request_items(
+                    table_name,
+                    KeysAndAttributes::builder()
+                        .set_keys(Some(pending))
+                        .projection_expression("#fieldName, #canonicalName")
+                        .expression_attribute_names("#fieldName", "fieldName")
+                        .expression_attribute_names("#canonicalName", "canonicalName")
+                        .build()?,
+                )
+                .send()
+                .await?;
+
+            names.extend(
+                response
+                    .responses
+                    .and_then(|mut items| items.remove(table_name))
+                    .unwrap_or_default()
+                    .into_iter()
+                    .filter_map(
+                        |mut sensor| match (sensor.remove("fieldName"), sensor.remove("canonicalName")) {
+                            (Some(S(field_name)), Some(S(name))) => Some((field_name, name)),
+                            _ => None,
+                        },
+                    ),
+            );
+
+            pending = response
+                .unprocessed_keys
+                .and_then(|mut keys| keys.remove(table_name))
+                .map(|keys| keys.keys)
+                .unwrap_or_default();
+            if !pending.is_empty() {
+                if attempt >= LOOKUP_ATTEMPTS {
+                    return Err(RuntimeError::UnprocessedItems(pending.len()));
+                }
+
+                sleep(backoff_delay(attempt)).await;
+                attempt += 1;
+            }
+        }
+    }
+
+    Ok(names)
+}
+
+#[doc = "Units assigned to vessel sensors, keyed by field name."]
+pub async fn assigned_units_of(dao: &DynamoDbDao, hash_key: &str) -> Result<HashMap<String, String>, RuntimeError> {
+    let mut units = HashMap::new();
+    let mut page_token = None;
+
+    loop {
+        let page = dao.query::<Sensor, _>(hash_key, page_token).await?;
+        units.extend(
+            page.items
+                .into_iter()
+                .filter_map(|sensor| sensor.unit.map(|unit| (sensor.field_name, unit))),
+        );
+
+        page_token = page.last_evaluated_key;
+        if page_token.is_none() {
+            return Ok(units);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::model::{Report, ReportValue};
+    use crate::parsers::fixture_context;
+    use crate::sensors::{timestamp_of, SeenSensor, SensorsSeen};
+    use chrono::DateTime;
+    use uuid::{uuid, Uuid};
+
+    const CUSTOMER_ID: Uuid = uuid!("00000000-0000-0000-0000-000000000000");
+    const VESSEL_ID: Uuid = uuid!("00000000-0000-0000-0000-000000000001");
+
+    fn report(field_name: &str, label: &str, recorded_at: i64) -> Report {
+        let context = fixture_context(CUSTOMER_ID, VESSEL_ID);
+
+        Report {
+            customer_id: CUSTOMER_ID,
+            vessel_id: VESSEL_ID,
+            report_name: "2024-01-05.daily".into(),
+            field_name: field_name.into(),
+            value: ReportValue::Number(1.0),
+            label: label.into(),
+            source: Some(context.source_of(DateTime::from_timestamp(recorded_at, 0).unwrap())),
+            unit: None,
+            normalized: None,
+        }
+    }
+
+    #[test]
+    fn record_seen_period() {
+        let mut seen = SensorsSeen::default();
+        seen.record(&report("1", "Fuel", 1704412800));
+        seen.record(&report("1", "Fuel (l)", 1704499200));
+        seen.record(&report("1", "Fuel (old)", 1704326400));
+        seen.record(&report("2", "Engine hours", 1704412800));
+
+        let mut untracked = report("3", "Speed", 1704412800);
+        untracked.source = None;
+        seen.record(&untracked);
+
+        assert_eq!(2, seen.sensors.len());
+        assert_eq!(
+            Some(&SeenSensor {
+                label: "Fuel (l)".into(),
+                first_seen_at: DateTime::from_timestamp(1704326400, 0).unwrap(),
+                last_seen_at: DateTime::from_timestamp(1704499200, 0).unwrap(),
+            }),
+            seen.sensors.get("1")
+        );
+    }
+
+    #[test]
+    fn comparable_timestamps() {
+        assert_eq!(
+            "2024-01-05T00:00:00Z",
+            timestamp_of(&DateTime::from_timestamp(1704412800, 0).unwrap())
+        );
+    }
+}
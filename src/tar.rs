@@ -0,0 +1,261 @@
+/*
+ * This file is part of the IVMS Online.
+ *
+ * @copyright 2024 © by Rafał Wrzeszcz - Wrzasq.pl.
+ */
+
+use crate::runtime_error::RuntimeError;
+use futures::io::{copy, sink};
+use futures::{AsyncRead, AsyncReadExt};
+use std::io::Result as IoResult;
+use std::pin::Pin;
+use std::str::from_utf8;
+use std::task::{Context, Poll};
+
+static BLOCK_SIZE: u64 = 512;
+// extension headers are read into memory before any entry is checked against archive limits
+static MAX_EXTENDED_HEADER_SIZE: u64 = 64 * 1024;
+
+#[doc = "Header of the tar archive entry."]
+pub struct TarEntry {
+    pub name: String,
+    pub size: u64,
+    #[doc = "Whether entry is a regular file (directories, links etc. don't have content to process)."]
+    pub regular: bool,
+}
+
+#[doc = "Minimal forward-only reader of ustar/GNU/pax tar streams."]
+pub struct TarReader<R> {
+    reader: R,
+    // bytes of current entry that were not read yet
+    remaining: u64,
+    padding: u64,
+}
+
+impl<R: AsyncRead + Unpin> TarReader<R> {
+    pub fn new(reader: R) -> Self {
+        Self {
+            reader,
+            remaining: 0,
+            padding: 0,
+        }
+    }
+
+    #[doc = "Skips the rest of current entry and reads next entry header. Returns `None` at the end of archive."]
+    pub async fn next_entry(&mut self) -> Result<Option<TarEntry>, RuntimeError> {
+        let mut long_name = None;
+
+        loop {
+            self.skip(self.remaining + self.padding).await?;
+
+            let mut header = [0; BLOCK_SIZE as usize];
+            self.reader.read_exact(&mut header).await?;
+
+            // archive is terminated with empty blocks
+            if header.iter().all(|byte| *byte == 0) {
+                return Ok(None);
+            }
+
+            if checksum_of(&header) != parse_octal(&header[148..156])? {
+                return Err(RuntimeError::TarError("header checksum mismatch".into()));
+            }
+
+            let size = parse_octal(&header[124..136])?;
+            self.remaining = size;
+            self.padding = (BLOCK_SIZE - size % BLOCK_SIZE) % BLOCK_SIZE;
+
+            if matches!(header[156], b'L' | b'x' | b'g') && size > MAX_EXTENDED_HEADER_SIZE {
+                return Err(RuntimeError::TarError(format!("extended header of {size} bytes")));
+            }
+
+            match header[156] {
+                // GNU long name and pax extended header precede the entry they describe
+                b'L' => long_name = Some(self.read_text().await?),
+                b'x' => {
+                    if let Some(path) = pax_path(&self.read_text().await?) {
+                        long_name = Some(path);
+                    }
+                }
+                // global pax header doesn't describe any particular entry
+                b'g' => {}
+                kind => {
+                    return Ok(Some(TarEntry {
+                        name: long_name.unwrap_or_else(|| header_name(&header)),
+                        size,
+                        regular: matches!(kind, b'0' | b'\0' | b'7'),
+                    }))
+                }
+            }
+        }
+    }
+
+    #[doc = "Reader of current entry content."]
+    pub fn entry_reader(&mut self) -> TarEntryReader<'_, R> {
+        TarEntryReader { tar: self }
+    }
+
+    async fn read_text(&mut self) -> Result<String, RuntimeError> {
+        let mut data = vec![];
+        self.entry_reader().read_to_end(&mut data).await?;
+
+        Ok(String::from_utf8_lossy(&data).trim_end_matches('\0').into())
+    }
+
+    async fn skip(&mut self, size: u64) -> Result<(), RuntimeError> {
+        if copy((&mut self.reader).take(size), &mut sink()).await? < size {
+            return Err(RuntimeError::TarError("unexpected end of archive".into()));
+        }
+        self.remaining = 0;
+        self.padding = 0;
+
+        Ok(())
+    }
+}
+
+#[doc = "Content of single tar entry."]
+pub struct TarEntryReader<'a, R> {
+    tar: &'a mut TarReader<R>,
+}
+
+impl<R: AsyncRead + Unpin> AsyncRead for TarEntryReader<'_, R> {
+    fn poll_read(mut self: Pin<&mut Self>, context: &mut Context<'_>, buf: &mut [u8]) -> Poll<IoResult<usize>> {
+        let limit = buf.len().min(usize::try_from(self.tar.remaining).unwrap_or(usize::MAX));
+        if limit == 0 {
+            return Poll::Ready(Ok(0));
+        }
+
+        let poll = Pin::new(&mut self.tar.reader).poll_read(context, &mut buf[..limit]);
+        if let Poll::Ready(Ok(size)) = poll {
+            if size == 0 {
+                return Poll::Ready(Err(std::io::ErrorKind::UnexpectedEof.into()));
+            }
+            self.tar.remaining -= size as u64;
+        }
+
+        poll
+    }
+}
+
+// checksum is computed with checksum field itself filled with spaces
+fn checksum_of(header: &[u8]) -> u64 {
+    header
+        .iter()
+        .enumerate()
+        .map(|(index, byte)| match index {
+            148..=155 => b' ' as u64,
+            _ => *byte as u64,
+        })
+        .sum()
+}
+
+fn parse_octal(field: &[u8]) -> Result<u64, RuntimeError> {
+    let text = from_utf8(field)
+        .map_err(|_| RuntimeError::TarError("non-ASCII numeric field".into()))?
+        .trim_matches(|character: char| character == '\0' || character == ' ');
+
+    if text.is_empty() {
+        Ok(0)
+    } else {
+        u64::from_str_radix(text, 8).map_err(|_| RuntimeError::TarError(format!("invalid numeric field {text}")))
+    }
+}
+
+fn text_field(field: &[u8]) -> &[u8] {
+    field.split(|byte| *byte == 0).next().unwrap_or_default()
+}
+
+fn header_name(header: &[u8]) -> String {
+    let name = String::from_utf8_lossy(text_field(&header[0..100]));
+    // ustar splits long paths into prefix and name
+    let prefix = match &header[257..262] {
+        b"ustar" => String::from_utf8_lossy(text_field(&header[345..500])),
+        _ => "".into(),
+    };
+
+    match prefix.is_empty() {
+        true => name.into(),
+        false => format!("{prefix}/{name}"),
+    }
+}
+
+// pax records have form of "{length} {key}={value}\n"
+fn pax_path(records: &str) -> Option<String> {
+    records
+        .lines()
+        .filter_map(|record| record.split_once(' ').map(|(_, pair)| pair))
+        .find_map(|pair| pair.strip_prefix("path="))
+        .map(String::from)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::runtime_error::RuntimeError;
+    use crate::tar::{checksum_of, parse_octal, pax_path, TarReader};
+    use async_compression::futures::bufread::GzipDecoder;
+    use futures::AsyncReadExt;
+
+    static FIXTURE: &[u8] = include_bytes!("../tests/fixtures/sync.tar.gz");
+
+    #[test]
+    fn parse_numeric_field() {
+        assert_eq!(420, parse_octal(b"0000644\0").unwrap());
+        assert_eq!(0, parse_octal(b"\0\0\0\0").unwrap());
+        assert!(parse_octal(b"invalid\0").is_err());
+    }
+
+    #[test]
+    fn parse_pax_path() {
+        assert_eq!(
+            Some("data/reports.json".into()),
+            pax_path("20 mtime=1704412800\n26 path=data/reports.json\n")
+        );
+        assert!(pax_path("20 mtime=1704412800\n").is_none());
+    }
+
+    #[tokio::test]
+    async fn read_entries() {
+        let mut tar = TarReader::new(GzipDecoder::new(FIXTURE));
+        let mut entries = vec![];
+
+        while let Some(entry) = tar.next_entry().await.unwrap() {
+            let mut data = String::new();
+            tar.entry_reader().read_to_string(&mut data).await.unwrap();
+            entries.push((entry.name, entry.regular, data.len() as u64 == entry.size));
+        }
+
+        assert_eq!(
+            vec![
+                ("data/".into(), false, true),
+                ("data/reports.json".into(), true, true),
+                // long name stored in GNU extension header
+                (format!("data/{}.txt", "notes-".repeat(20)), true, true),
+            ],
+            entries
+        );
+    }
+
+    #[tokio::test]
+    async fn read_truncated() {
+        let mut data = vec![];
+        GzipDecoder::new(FIXTURE).read_to_end(&mut data).await.unwrap();
+
+        let mut tar = TarReader::new(&data[..700]);
+        assert!(tar.next_entry().await.unwrap().is_some());
+        assert!(tar.next_entry().await.is_err());
+    }
+
+    #[tokio::test]
+    async fn read_oversized_extended_header() {
+        for kind in [b'L', b'x', b'g'] {
+            let mut header = [0; 512];
+            header[..9].copy_from_slice(b"PaxHeader");
+            header[124..136].copy_from_slice(b"00004000000\0");
+            header[156] = kind;
+            let checksum = format!("{:06o}\0 ", checksum_of(&header));
+            header[148..156].copy_from_slice(checksum.as_bytes());
+
+            let mut tar = TarReader::new(&header[..]);
+            assert!(matches!(tar.next_entry().await, Err(RuntimeError::TarError(_))));
+        }
+    }
+}
@@ -0,0 +1,165 @@
+/*
+ * This file is part of the IVMS Online.
+ *
+ * @copyright 2024 © by Rafał Wrzeszcz - Wrzasq.pl.
+ */
+
+use crate::runtime_error::RuntimeError;
+use chrono::{DateTime, FixedOffset, NaiveDate, Utc};
+use chrono_tz::Tz;
+use serde_json::from_str;
+use std::collections::HashMap;
+use std::env::{var, VarError};
+use std::fmt::{Display, Formatter, Result as FmtResult};
+use std::str::FromStr;
+use uuid::Uuid;
+
+static DEFAULT_KEY: &str = "*";
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[doc = "Timezone in which report dates are determined - either IANA zone (`Asia/Singapore`) or fixed UTC offset \
+(`+08:00`)."]
+pub enum ReportTimezone {
+    Named(Tz),
+    Offset(FixedOffset),
+}
+
+impl ReportTimezone {
+    #[doc = "Local date of the vessel at given time."]
+    pub fn date_of(&self, time: &DateTime<Utc>) -> NaiveDate {
+        match self {
+            Self::Named(zone) => time.with_timezone(zone).date_naive(),
+            Self::Offset(offset) => time.with_timezone(offset).date_naive(),
+        }
+    }
+}
+
+impl Default for ReportTimezone {
+    fn default() -> Self {
+        Self::Named(Tz::UTC)
+    }
+}
+
+impl FromStr for ReportTimezone {
+    type Err = RuntimeError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        FixedOffset::from_str(value)
+            .map(Self::Offset)
+            .or_else(|_| Tz::from_str(value).map(Self::Named))
+            .map_err(|_| RuntimeError::InvalidTimezone(value.into()))
+    }
+}
+
+impl Display for ReportTimezone {
+    fn fmt(&self, formatter: &mut Formatter<'_>) -> FmtResult {
+        match self {
+            Self::Named(zone) => write!(formatter, "{}", zone.name()),
+            Self::Offset(offset) => write!(formatter, "{offset}"),
+        }
+    }
+}
+
+#[derive(Default)]
+#[doc = "Report timezones of all vessels."]
+pub struct VesselTimezones {
+    default: ReportTimezone,
+    vessels: HashMap<Uuid, ReportTimezone>,
+}
+
+impl VesselTimezones {
+    #[doc = "Reads timezones from `VESSEL_TIMEZONES` variable, when it's not set all vessels use UTC."]
+    pub fn from_env() -> Result<Self, RuntimeError> {
+        match var("VESSEL_TIMEZONES") {
+            Ok(json) => Self::from_json(json.as_str()),
+            Err(VarError::NotPresent) => Ok(Self::default()),
+            Err(error) => Err(error.into()),
+        }
+    }
+
+    #[doc = "Parses timezones keyed by vessel ID, `*` key holds timezone of vessels without own one."]
+    pub fn from_json(json: &str) -> Result<Self, RuntimeError> {
+        let mut timezones = Self::default();
+
+        for (key, timezone) in from_str::<HashMap<String, String>>(json)? {
+            let timezone = ReportTimezone::from_str(timezone.as_str())?;
+
+            if key == DEFAULT_KEY {
+                timezones.default = timezone;
+            } else {
+                timezones.vessels.insert(Uuid::parse_str(key.as_str())?, timezone);
+            }
+        }
+
+        Ok(timezones)
+    }
+
+    pub fn for_vessel(&self, vessel_id: &Uuid) -> ReportTimezone {
+        *self.vessels.get(vessel_id).unwrap_or(&self.default)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::runtime_error::RuntimeError;
+    use crate::timezone::{ReportTimezone, VesselTimezones};
+    use chrono::{DateTime, NaiveDate};
+    use std::str::FromStr;
+    use uuid::{uuid, Uuid};
+
+    const VESSEL_ID: Uuid = uuid!("00000000-0000-0000-0000-000000000001");
+    const OTHER_VESSEL_ID: Uuid = uuid!("00000000-0000-0000-0000-000000000002");
+
+    #[test]
+    fn utc_by_default() {
+        let timezone = VesselTimezones::default().for_vessel(&VESSEL_ID);
+
+        assert_eq!("UTC", timezone.to_string());
+        assert_eq!(
+            NaiveDate::from_ymd_opt(2024, 1, 5).unwrap(),
+            timezone.date_of(&DateTime::from_timestamp(1704499199, 0).unwrap())
+        );
+    }
+
+    #[test]
+    fn local_date() {
+        // 2024-01-05T20:00:00Z
+        let time = DateTime::from_timestamp(1704484800, 0).unwrap();
+
+        assert_eq!(
+            NaiveDate::from_ymd_opt(2024, 1, 6).unwrap(),
+            ReportTimezone::from_str("Asia/Singapore").unwrap().date_of(&time)
+        );
+        assert_eq!(
+            NaiveDate::from_ymd_opt(2024, 1, 6).unwrap(),
+            ReportTimezone::from_str("+05:30").unwrap().date_of(&time)
+        );
+        assert_eq!(
+            NaiveDate::from_ymd_opt(2024, 1, 5).unwrap(),
+            ReportTimezone::from_str("America/New_York").unwrap().date_of(&time)
+        );
+    }
+
+    #[test]
+    fn vessel_timezones() {
+        let timezones =
+            VesselTimezones::from_json(r#"{"*": "+02:00", "00000000-0000-0000-0000-000000000001": "Asia/Tokyo"}"#)
+                .unwrap();
+
+        assert_eq!("Asia/Tokyo", timezones.for_vessel(&VESSEL_ID).to_string());
+        assert_eq!("+02:00", timezones.for_vessel(&OTHER_VESSEL_ID).to_string());
+    }
+
+    #[test]
+    fn invalid_timezones() {
+        assert!(matches!(
+            VesselTimezones::from_json(r#"{"*": "Mars/Olympus"}"#),
+            Err(RuntimeError::InvalidTimezone(_))
+        ));
+        assert!(matches!(
+            VesselTimezones::from_json(r#"{"vessel": "UTC"}"#),
+            Err(RuntimeError::UuidError(_))
+        ));
+        assert!(VesselTimezones::from_json(r#"{"*": 8}"#).is_err());
+    }
+}
@@ -0,0 +1,201 @@
+/*
+ * This file is part of the IVMS Online.
+ *
+ * @copyright 2024 © by Rafał Wrzeszcz - Wrzasq.pl.
+ */
+
+use crate::model::{NormalizedValue, ReportValue};
+use lazy_regex::regex_captures;
+
+// linear conversion into SI unit: `value * factor + offset`
+struct UnitRule {
+    symbols: &'static [&'static str],
+    si_unit: Option<&'static str>,
+    factor: f64,
+    offset: f64,
+}
+
+const fn to_si(symbols: &'static [&'static str], si_unit: &'static str, factor: f64, offset: f64) -> UnitRule {
+    UnitRule {
+        symbols,
+        si_unit: Some(si_unit),
+        factor,
+        offset,
+    }
+}
+
+// recognized, but there is no SI counterpart to convert to
+const fn non_si(symbols: &'static [&'static str]) -> UnitRule {
+    UnitRule {
+        symbols,
+        si_unit: None,
+        factor: 1.0,
+        offset: 0.0,
+    }
+}
+
+static UNITS: &[UnitRule] = &[
+    to_si(&["l", "L", "ltr"], "m³", 0.001, 0.0),
+    to_si(&["m3", "m³"], "m³", 1.0, 0.0),
+    to_si(&["l/h", "L/h"], "m³/s", 0.001 / 3600.0, 0.0),
+    to_si(&["m3/h", "m³/h"], "m³/s", 1.0 / 3600.0, 0.0),
+    to_si(&["°C", "℃", "degC"], "K", 1.0, 273.15),
+    to_si(&["°F", "℉", "degF"], "K", 5.0 / 9.0, 273.15 - 32.0 * 5.0 / 9.0),
+    to_si(&["K"], "K", 1.0, 0.0),
+    to_si(&["W"], "W", 1.0, 0.0),
+    to_si(&["kW"], "W", 1000.0, 0.0),
+    to_si(&["MW"], "W", 1000000.0, 0.0),
+    to_si(&["kWh"], "J", 3600000.0, 0.0),
+    to_si(&["V"], "V", 1.0, 0.0),
+    to_si(&["A"], "A", 1.0, 0.0),
+    to_si(&["Hz"], "Hz", 1.0, 0.0),
+    to_si(&["Pa"], "Pa", 1.0, 0.0),
+    to_si(&["kPa"], "Pa", 1000.0, 0.0),
+    to_si(&["mbar"], "Pa", 100.0, 0.0),
+    to_si(&["bar"], "Pa", 100000.0, 0.0),
+    to_si(&["psi"], "Pa", 6894.757293168, 0.0),
+    to_si(&["m/s"], "m/s", 1.0, 0.0),
+    to_si(&["km/h"], "m/s", 1.0 / 3.6, 0.0),
+    to_si(&["kn", "kt", "kts"], "m/s", 1852.0 / 3600.0, 0.0),
+    to_si(&["m"], "m", 1.0, 0.0),
+    to_si(&["km"], "m", 1000.0, 0.0),
+    to_si(&["NM", "nmi"], "m", 1852.0, 0.0),
+    to_si(&["s"], "s", 1.0, 0.0),
+    to_si(&["min"], "s", 60.0, 0.0),
+    to_si(&["h"], "s", 3600.0, 0.0),
+    to_si(&["g"], "kg", 0.001, 0.0),
+    to_si(&["kg"], "kg", 1.0, 0.0),
+    to_si(&["t"], "kg", 1000.0, 0.0),
+    non_si(&["%"]),
+    non_si(&["rpm"]),
+    non_si(&["ppm"]),
+];
+
+fn rule_of(unit: &str) -> Option<&'static UnitRule> {
+    UNITS.iter().find(|rule| rule.symbols.contains(&unit))
+}
+
+// characters that only units contain, separators used in plain identifiers (`ME-1`, `No.2`) don't count
+fn is_unit_symbol(character: char) -> bool {
+    !character.is_alphanumeric() && !"-_#.,".contains(character)
+}
+
+#[doc = "Extracts unit embedded in sensor label - either in brackets at the end, when it's known or contains a unit \
+symbol (`Fuel (l)`, `Power [kW]`, `Flow (kg/min)`), or as known trailing symbol with a non-letter character \
+(`Temp °F`, `Load %`)."]
+pub fn unit_of(label: &str) -> Option<String> {
+    // brackets often hold plain identifiers as well (`Engine (ME1)`, `Pump [2]`)
+    if let Some((_, unit)) = regex_captures!(r"[(\[]([^\s()\[\]]{1,10})[)\]]\s*$", label) {
+        return (rule_of(unit).is_some() || unit.chars().any(is_unit_symbol)).then(|| unit.into());
+    }
+
+    // plain trailing words are too ambiguous ("Engine hours"), so only symbols are taken
+    regex_captures!(r"\s(\S+)$", label.trim_end())
+        .map(|(_, symbol)| symbol)
+        .filter(|symbol| symbol.chars().any(|character| !character.is_alphabetic()))
+        .filter(|symbol| rule_of(symbol).is_some())
+        .map(String::from)
+}
+
+fn convert(value: &ReportValue, rule: &UnitRule) -> Option<ReportValue> {
+    match value {
+        ReportValue::Number(number) => Some(ReportValue::Number(number * rule.factor + rule.offset)),
+        // missing element of multi-element reading stays missing
+        ReportValue::Null => Some(ReportValue::Null),
+        ReportValue::List(values) => values
+            .iter()
+            .map(|value| convert(value, rule))
+            .collect::<Option<Vec<ReportValue>>>()
+            .map(ReportValue::List),
+        ReportValue::Bool(_) | ReportValue::Text(_) => None,
+    }
+}
+
+#[doc = "Converts numeric value into SI unit, if there is a known conversion for given unit."]
+pub fn normalize(value: &ReportValue, unit: &str) -> Option<NormalizedValue> {
+    let rule = rule_of(unit)?;
+    let si_unit = rule.si_unit?;
+
+    match value {
+        ReportValue::Null => None,
+        value => convert(value, rule).map(|value| NormalizedValue {
+            value,
+            unit: si_unit.into(),
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::model::ReportValue;
+    use crate::units::{normalize, unit_of};
+
+    fn assert_normalized(expected: f64, si_unit: &str, value: f64, unit: &str) {
+        let normalized = normalize(&ReportValue::Number(value), unit).unwrap();
+
+        assert_eq!(si_unit, normalized.unit);
+        match normalized.value {
+            ReportValue::Number(number) => assert!((number - expected).abs() < 1e-9, "{number} != {expected}"),
+            value => panic!("Unexpected value {value:?}"),
+        }
+    }
+
+    #[test]
+    fn extract_unit() {
+        assert_eq!(Some("l".into()), unit_of("Fuel (l)"));
+        assert_eq!(Some("kW".into()), unit_of("Power [kW] "));
+        assert_eq!(Some("°F".into()), unit_of("Temp °F"));
+        assert_eq!(Some("%".into()), unit_of("Engine load %"));
+        assert_eq!(Some("m³/h".into()), unit_of("Fuel flow m³/h"));
+        assert_eq!(Some("ppm".into()), unit_of("NOx (ppm)"));
+        // unknown bracketed units are kept, even though they can't be converted
+        assert_eq!(Some("kg/min".into()), unit_of("Flow (kg/min)"));
+    }
+
+    #[test]
+    fn no_unit() {
+        assert!(unit_of("Engine hours").is_none());
+        assert!(unit_of("Fuel").is_none());
+        assert!(unit_of("Generator (port side)").is_none());
+        assert!(unit_of("Generator (port)").is_none());
+        assert!(unit_of("Engine (ME1)").is_none());
+        assert!(unit_of("Engine (ME-1)").is_none());
+        assert!(unit_of("Pump [2]").is_none());
+        assert!(unit_of("Pump 2").is_none());
+        assert!(unit_of("").is_none());
+    }
+
+    #[test]
+    fn normalize_numbers() {
+        assert_normalized(0.0125, "m³", 12.5, "l");
+        assert_normalized(1500.0, "W", 1.5, "kW");
+        assert_normalized(293.15, "K", 20.0, "°C");
+        assert_normalized(273.15, "K", 32.0, "°F");
+        assert_normalized(200000.0, "Pa", 2.0, "bar");
+        assert_normalized(5.144444444, "m/s", 10.0, "kn");
+        assert_normalized(7200.0, "s", 2.0, "h");
+    }
+
+    #[test]
+    fn normalize_list() {
+        let normalized = normalize(
+            &ReportValue::List(vec![ReportValue::Number(1.0), ReportValue::Null]),
+            "kW",
+        )
+        .unwrap();
+
+        assert_eq!(
+            ReportValue::List(vec![ReportValue::Number(1000.0), ReportValue::Null]),
+            normalized.value
+        );
+    }
+
+    #[test]
+    fn not_normalized() {
+        assert!(normalize(&ReportValue::Number(80.0), "%").is_none());
+        assert!(normalize(&ReportValue::Number(80.0), "ppm").is_none());
+        assert!(normalize(&ReportValue::Text("on".into()), "kW").is_none());
+        assert!(normalize(&ReportValue::Null, "kW").is_none());
+        assert!(normalize(&ReportValue::List(vec![ReportValue::Bool(true)]), "kW").is_none());
+    }
+}